@@ -0,0 +1,153 @@
+//! Rendering animations offline, a frame at a time at a fixed frame rate
+//! instead of however fast the window keeps up.
+
+use std::{str::FromStr, time::Duration};
+
+use crate::Params;
+
+/// A clock that moves on by exactly one frame per frame, so frame `n` is
+/// always at the same time however long it took to render.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FrameClock {
+    pub fps: f64,
+}
+
+impl FrameClock {
+    /// The time frame `frame` shows.
+    pub fn time(&self, frame: u32) -> Duration {
+        Duration::from_secs_f64(frame as f64 / self.fps)
+    }
+
+    /// How many frames it takes to show all of `duration`, counting one at
+    /// the very start and, if it lands on a frame, the very end.
+    pub fn frames(&self, duration: Duration) -> u32 {
+        // Rounded a little up first, so a duration that's a whole number
+        // of frames doesn't lose its last one to rounding
+        (duration.as_secs_f64() * self.fps + 1e-6).floor() as u32 + 1
+    }
+}
+
+/// A parameter going linearly from one value to another over the whole
+/// animation, written `name=from:to` with `name` one of `Params::entries`.
+///
+/// The values can be several comma separated numbers for parameters that
+/// have them, like `julia_c=0,0,0,0:-0.2,0.6,0.2,0`. Parameters that only
+/// take whole numbers get rounded, so `max_iterations=4:16` works too.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Track {
+    pub name: String,
+    pub from: Vec<f64>,
+    pub to: Vec<f64>,
+    whole: bool,
+}
+
+impl Track {
+    /// Sets the parameter to its value a fraction `t` of the way through.
+    pub fn apply(&self, params: &mut Params, t: f64) -> Result<(), String> {
+        let values: Vec<String> = self
+            .from
+            .iter()
+            .zip(&self.to)
+            .map(|(from, to)| {
+                let value = from + (to - from) * t;
+                if self.whole {
+                    value.round().to_string()
+                } else {
+                    (value as f32).to_string()
+                }
+            })
+            .collect();
+        params.set(&self.name, &values.join(","))
+    }
+}
+
+impl FromStr for Track {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, values) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected name=from:to but got {s:?}"))?;
+        let (from, to) = values
+            .split_once(':')
+            .ok_or_else(|| format!("expected from:to but got {values:?}"))?;
+
+        let parse = |values: &str| {
+            values
+                .split(',')
+                .map(|v| v.trim().parse::<f64>().map_err(|e| format!("{v:?}: {e}")))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        let name = name.trim();
+        let from = parse(from)?;
+        // Whether the parameter takes whole numbers only, which is whether
+        // it refuses a fraction
+        let fraction = vec!["0.5"; from.len()].join(",");
+        let track = Self {
+            name: name.to_string(),
+            whole: Params::default().set(name, &fraction).is_err(),
+            from,
+            to: parse(to)?,
+        };
+        if track.from.len() != track.to.len() {
+            return Err(format!(
+                "{} numbers to start with but {} to end with",
+                track.from.len(),
+                track.to.len()
+            ));
+        }
+
+        // Catches a misspelled name before anything gets rendered
+        track.apply(&mut Params::default(), 0.0)?;
+        Ok(track)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_land_on_the_end() {
+        let clock = FrameClock { fps: 30.0 };
+
+        assert_eq!(clock.frames(Duration::from_secs(2)), 61);
+        assert_eq!(clock.frames(Duration::from_millis(2010)), 61);
+        assert_eq!(clock.frames(Duration::ZERO), 1);
+        assert_eq!(clock.time(60), Duration::from_secs(2));
+        assert_eq!(clock.time(15), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn tracks_go_from_one_value_to_the_other() {
+        let mut params = Params::default();
+        let track: Track = "julia_c=0,0,0,0:-0.2,0.6,0.2,1".parse().unwrap();
+
+        track.apply(&mut params, 0.5).unwrap();
+        assert_eq!(params.julia_c, [-0.1, 0.3, 0.1, 0.5]);
+
+        let track: Track = "max_iterations=4:16".parse().unwrap();
+        track.apply(&mut params, 0.3).unwrap();
+        assert_eq!(params.max_iterations, 8);
+    }
+
+    #[test]
+    fn only_whole_number_params_get_rounded() {
+        let mut params = Params::default();
+        let track: Track = "slice_w=0:1".parse().unwrap();
+
+        track.apply(&mut params, 0.3).unwrap();
+        assert_eq!(params.slice_w, 0.3);
+
+        let track: Track = "max_steps=0.0:10.0".parse().unwrap();
+        track.apply(&mut params, 0.34).unwrap();
+        assert_eq!(params.max_steps, 3);
+    }
+
+    #[test]
+    fn bad_tracks_are_errors() {
+        for track in ["power=2", "power=2,3:4", "colour=0:1", "power=a:3"] {
+            assert!(track.parse::<Track>().is_err(), "{track}");
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use std::{fs, path::PathBuf, time::Duration};
+
+use clap::Parser;
+use mandelbrot::{
+    animation::{FrameClock, Track},
+    bookmark::Bookmarks,
+    cpu,
+    headless::{HeadlessError, HeadlessRenderer},
+    path::CameraPath,
+    Camera, Params,
+};
+
+/// Renders a camera path to numbered PNGs, one per frame at a fixed frame rate, without
+/// opening a window. Frames that are already there are skipped, so running it again after
+/// it was interrupted carries on where it stopped
+#[derive(Parser)]
+#[command(version)]
+struct Args {
+    /// Camera path file recorded with G
+    #[arg(long, value_name = "FILE", default_value = "path.txt")]
+    path: PathBuf,
+
+    /// Frames per second
+    #[arg(long, default_value_t = 30.0, value_parser = parse_fps)]
+    fps: f64,
+
+    /// How many seconds to render, the whole path if left out
+    #[arg(long, value_parser = parse_duration)]
+    duration: Option<f64>,
+
+    /// Render with the parameters of this bookmark instead of the defaults
+    #[arg(long, value_name = "SLOT")]
+    bookmark: Option<u8>,
+
+    /// Where the bookmarks are kept
+    #[arg(long, value_name = "FILE", default_value = "bookmarks.txt")]
+    bookmarks: PathBuf,
+
+    /// Animate a parameter from one value to another over the whole animation, like
+    /// slice_w=0:0.5 or julia_c=0,0,0,0:-0.2,0.6,0.2,0. Can be repeated
+    #[arg(long, value_name = "NAME=FROM:TO", allow_hyphen_values = true)]
+    animate: Vec<Track>,
+
+    /// Width of the frames in pixels
+    #[arg(long, default_value_t = 1920, value_parser = clap::value_parser!(u32).range(1..))]
+    width: u32,
+
+    /// Height of the frames in pixels
+    #[arg(long, default_value_t = 1080, value_parser = clap::value_parser!(u32).range(1..))]
+    height: u32,
+
+    /// Directory to save the frames in, as frame_00000.png, frame_00001.png and so on
+    #[arg(short, long, default_value = "frames")]
+    output: PathBuf,
+
+    /// Render on the CPU instead of the GPU
+    #[arg(long)]
+    cpu: bool,
+}
+
+/// Parses a frame rate, which has to be above 0 to ever get past the first frame
+fn parse_fps(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>().map_err(|e| e.to_string())? {
+        fps if fps.is_finite() && fps > 0.0 => Ok(fps),
+        _ => Err("must be a number above 0".to_string()),
+    }
+}
+
+/// Parses a number of seconds, which can't be negative
+fn parse_duration(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>().map_err(|e| e.to_string())? {
+        duration if duration.is_finite() && duration >= 0.0 => Ok(duration),
+        _ => Err("must be a number of seconds, 0 or more".to_string()),
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::init();
+    let args = Args::parse();
+
+    let path = CameraPath::load(&args.path)?;
+    if path.keyframes.is_empty() {
+        return Err(format!("{} has no keyframes", args.path.display()).into());
+    }
+    let params = match args.bookmark {
+        Some(slot) => Bookmarks::load(&args.bookmarks)?
+            .get(slot)
+            .ok_or_else(|| format!("no bookmark {slot} in {}", args.bookmarks.display()))?
+            .params
+            .clone(),
+        None => Params::default(),
+    };
+
+    let clock = FrameClock { fps: args.fps };
+    let duration = match args.duration {
+        Some(duration) => Duration::try_from_secs_f64(duration)?,
+        None => path.duration(),
+    };
+    let frames = clock.frames(duration);
+
+    let renderer = if args.cpu {
+        None
+    } else {
+        match pollster::block_on(HeadlessRenderer::new()) {
+            Ok(renderer) => Some(renderer),
+            Err(HeadlessError::NoAdapter) => {
+                log::warn!("No graphics adapter found, falling back to the CPU");
+                None
+            }
+            Err(e) => return Err(e.into()),
+        }
+    };
+
+    fs::create_dir_all(&args.output)?;
+    let mut camera = Camera::default();
+    for frame in 0..frames {
+        let file = args.output.join(format!("frame_{frame:05}.png"));
+        if file.exists() {
+            continue;
+        }
+
+        let time = clock.time(frame);
+        path.place(&mut camera, time);
+        let mut params = params.clone();
+        let t = if duration.is_zero() {
+            0.0
+        } else {
+            time.as_secs_f64() / duration.as_secs_f64()
+        };
+        for track in &args.animate {
+            track.apply(&mut params, t)?;
+        }
+
+        let image = match &renderer {
+            Some(renderer) => renderer.render(&camera, &params, args.width, args.height)?,
+            None => cpu::render(&camera, &params, args.width, args.height),
+        };
+        // Written under another name first, so a frame that was cut off
+        // halfway isn't mistaken for a finished one next time
+        let partial = file.with_extension("png.partial");
+        image.save_png(&partial)?;
+        fs::rename(&partial, &file)?;
+        log::info!("frame {}/{frames}", frame + 1);
+    }
+
+    Ok(())
+}
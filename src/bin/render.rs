@@ -0,0 +1,197 @@
+use std::{fmt::Display, path::PathBuf, str::FromStr, time::Duration};
+
+use clap::Parser;
+use mandelbrot::{
+    cpu,
+    flip::{Flip, FlipSchedule},
+    headless::{HeadlessError, HeadlessRenderer},
+    path::CameraPath,
+    rotation, Camera, Derivative, Estimator, Params, Position, Precision, Shading,
+};
+
+/// Renders a single frame of the fractal to an image, without opening a window
+#[derive(Parser)]
+#[command(version)]
+struct Args {
+    /// Camera position, which can have as many digits as deep zooms need. W is 0 if left out
+    #[arg(
+        long,
+        value_name = "X,Y,Z[,W]",
+        allow_hyphen_values = true,
+        default_value = "0,0,-3"
+    )]
+    pos: Position,
+
+    /// Rotation around the vertical axis, in degrees
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values = true)]
+    yaw: f32,
+
+    /// Rotation up or down, in degrees
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values = true)]
+    pitch: f32,
+
+    /// Rotation clockwise around the direction the camera looks in, in degrees
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values = true)]
+    roll: f32,
+
+    /// Rotation of the camera's right, up and forward towards the w axis it doesn't see, in
+    /// degrees
+    #[arg(long, value_name = "XW,YW,ZW", value_parser = parse_floats::<3, f32>, allow_hyphen_values = true, default_value = "0,0,0")]
+    w_angles: [f32; 3],
+
+    /// Horizontal field of view, in degrees
+    #[arg(long, default_value_t = 90.0)]
+    fov: f32,
+
+    /// Camera path file recorded with G, which the camera follows instead of the options above
+    #[arg(long, value_name = "FILE")]
+    path: Option<PathBuf>,
+
+    /// How far into the path to render, in seconds
+    #[arg(long, default_value = "0", value_parser = parse_duration, requires = "path")]
+    time: Duration,
+
+    /// Maximum number of iterations of the fractal formula
+    #[arg(long, default_value_t = Params::default().max_iterations)]
+    iterations: u32,
+
+    /// How to color the image: steps, normals, phong, depth or iterations
+    #[arg(long, default_value_t = Shading::default())]
+    shading: Shading,
+
+    /// Where the 3D slice sits along the fourth axis
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values = true)]
+    slice_w: f32,
+
+    /// Rotation of the slice in the xy, xz, xw, yz, yw and zw planes, in degrees
+    #[arg(long, value_name = "XY,XZ,XW,YZ,YW,ZW", value_parser = parse_floats::<6, f32>, allow_hyphen_values = true, default_value = "0,0,0,0,0,0")]
+    slice_angles: [f32; 6],
+
+    /// Render the Julia set for this quaternion constant instead of the mandelbrot set
+    #[arg(long, value_name = "R,I,J,K", value_parser = parse_floats::<4, f32>, allow_hyphen_values = true)]
+    julia: Option<[f32; 4]>,
+
+    /// Map applied to z between iterations, either a signed permutation like x,-z,-y,w or 16
+    /// matrix entries column by column. Repeat it to alternate between several flips
+    #[arg(long, allow_hyphen_values = true)]
+    flip: Vec<Flip>,
+
+    /// When to flip: once, every or every-N iterations
+    #[arg(long, default_value_t = FlipSchedule::default())]
+    flip_schedule: FlipSchedule,
+
+    /// The n in z^n + c, doesn't have to be a whole number
+    #[arg(long, default_value_t = Params::default().power, allow_hyphen_values = true)]
+    power: f32,
+
+    /// What the distance estimate tracks as the derivative: quaternion or jacobian
+    #[arg(long, default_value_t = Derivative::default())]
+    derivative: Derivative,
+
+    /// Distance estimator formula: log, linear or gradient
+    #[arg(long, default_value_t = Estimator::default())]
+    estimator: Estimator,
+
+    /// Multiplies every distance estimate, lower it to take smaller steps
+    #[arg(long, default_value_t = Params::default().fudge)]
+    fudge: f32,
+
+    /// How close a ray has to get to the set to count as a hit. Lower it along
+    /// with the field of view to zoom in
+    #[arg(long, default_value_t = Params::default().min_distance)]
+    min_distance: f32,
+
+    /// single, double for emulated double precision on deep zooms, or perturbation for zooms
+    /// deeper still with the camera close to the surface
+    #[arg(long, default_value_t = Precision::default())]
+    precision: Precision,
+
+    /// Width of the image in pixels
+    #[arg(long, default_value_t = 1280, value_parser = clap::value_parser!(u32).range(1..))]
+    width: u32,
+
+    /// Height of the image in pixels
+    #[arg(long, default_value_t = 720, value_parser = clap::value_parser!(u32).range(1..))]
+    height: u32,
+
+    /// Where to save the PNG
+    #[arg(short, long, default_value = "render.png")]
+    output: PathBuf,
+
+    /// Render on the CPU instead of the GPU
+    #[arg(long)]
+    cpu: bool,
+}
+
+/// Parses a number of seconds, which can't be negative
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let seconds = s.trim().parse::<f32>().map_err(|e| e.to_string())?;
+    Duration::try_from_secs_f32(seconds)
+        .map_err(|_| "must be a number of seconds, 0 or more".to_string())
+}
+
+/// Parses `N` comma separated numbers, like `1.0,-2,3e-4`
+fn parse_floats<const N: usize, T>(s: &str) -> Result<[T; N], String>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let values = s
+        .split(',')
+        .map(|v| v.trim().parse::<T>().map_err(|e| format!("{v:?}: {e}")))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    values
+        .try_into()
+        .map_err(|v: Vec<T>| format!("expected {N} numbers but got {}", v.len()))
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::init();
+    let args = Args::parse();
+
+    let mut camera = Camera::new(args.pos, args.yaw.to_radians(), args.pitch.to_radians())
+        .with_roll(args.roll.to_radians())
+        .with_w_angles(args.w_angles.map(f32::to_radians))
+        .with_fov(args.fov.to_radians());
+    if let Some(path) = &args.path {
+        CameraPath::load(path)?.place(&mut camera, args.time);
+    }
+    let mut params = Params {
+        max_iterations: args.iterations,
+        shading: args.shading,
+        slice_w: args.slice_w,
+        slice_rotation: rotation::from_angles(args.slice_angles.map(f32::to_radians)).into(),
+        flip_schedule: args.flip_schedule,
+        power: args.power,
+        derivative: args.derivative,
+        estimator: args.estimator,
+        fudge: args.fudge,
+        min_distance: args.min_distance,
+        precision: args.precision,
+        ..Params::default()
+    };
+    if !args.flip.is_empty() {
+        params.flips = args.flip;
+    }
+    if let Some(c) = args.julia {
+        params.julia = true;
+        params.julia_c = c;
+    }
+
+    let image = if args.cpu {
+        cpu::render(&camera, &params, args.width, args.height)
+    } else {
+        match pollster::block_on(HeadlessRenderer::new()) {
+            Ok(renderer) => renderer.render(&camera, &params, args.width, args.height)?,
+            Err(HeadlessError::NoAdapter) => {
+                log::warn!("No graphics adapter found, falling back to the CPU");
+                cpu::render(&camera, &params, args.width, args.height)
+            }
+            Err(e) => return Err(e.into()),
+        }
+    };
+    image.save_png(&args.output)?;
+
+    Ok(())
+}
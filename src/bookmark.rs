@@ -0,0 +1,272 @@
+//! Numbered views to come back to, kept in a text file like
+//!
+//! ```text
+//! [1]
+//! pos = 0.1,-0.25,-1.5,0
+//! orientation_left = 1,0,0,0
+//! orientation_right = 1,0,0,0
+//! fov = 90
+//! speed = 1
+//! max_iterations = 16
+//! ...
+//! ```
+//!
+//! with the orientation's quaternions as `s,x,y,z`, the field of view in
+//! degrees and the rest of the keys being `Params::entries`. Parameters
+//! left out keep their defaults, so older files still load. Camera paths
+//! are kept the same way.
+
+use std::{collections::BTreeMap, fmt, fs, io, path::Path, str::FromStr};
+
+use cgmath::Quaternion;
+
+use crate::{Camera, Params, Position};
+
+/// Everything needed to get back to a view: where the camera was, how fast
+/// it was flying and what it was looking at.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bookmark {
+    pub camera: Camera,
+    pub speed: f32,
+    pub params: Params,
+}
+
+impl Bookmark {
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "pos" | "orientation_left" | "orientation_right" | "fov" => {
+                set_camera(&mut self.camera, key, value)?
+            }
+            "speed" => self.speed = parse_f32(value)?,
+            _ => self.params.set(key, value)?,
+        }
+        Ok(())
+    }
+}
+
+impl Default for Bookmark {
+    fn default() -> Self {
+        Self {
+            camera: Camera::default(),
+            speed: 1.0,
+            params: Params::default(),
+        }
+    }
+}
+
+/// Writes the lines for where `camera` is and which way it's looking.
+pub(crate) fn write_camera(f: &mut fmt::Formatter<'_>, camera: &Camera) -> fmt::Result {
+    let orientation = camera.orientation();
+    writeln!(f, "pos = {}", camera.position())?;
+    writeln!(
+        f,
+        "orientation_left = {}",
+        write_quaternion(orientation.left)
+    )?;
+    writeln!(
+        f,
+        "orientation_right = {}",
+        write_quaternion(orientation.right)
+    )?;
+    writeln!(f, "fov = {}", camera.fov.to_degrees())
+}
+
+/// Reads one of the lines `write_camera` writes.
+pub(crate) fn set_camera(camera: &mut Camera, key: &str, value: &str) -> Result<(), String> {
+    match key {
+        "pos" => camera.set_position(value.parse::<Position>()?),
+        "orientation_left" => {
+            let mut orientation = camera.orientation();
+            orientation.left = parse_quaternion(value)?;
+            camera.set_orientation(orientation);
+        }
+        "orientation_right" => {
+            let mut orientation = camera.orientation();
+            orientation.right = parse_quaternion(value)?;
+            camera.set_orientation(orientation);
+        }
+        "fov" => camera.fov = parse_f32(value)?.to_radians(),
+        _ => return Err(format!("unknown key {key:?}")),
+    }
+    Ok(())
+}
+
+/// Splits a file into its `[header]` sections, starting each with
+/// `new(header)` and passing it every `key = value` line up to the next one.
+/// Blank lines and lines starting with `#` are skipped.
+pub(crate) fn parse_sections<T>(
+    s: &str,
+    mut new: impl FnMut(&str) -> Result<T, String>,
+    mut set: impl FnMut(&mut T, &str, &str) -> Result<(), String>,
+) -> Result<Vec<T>, String> {
+    let mut sections = Vec::new();
+
+    for (number, line) in s.lines().enumerate() {
+        let line = line.trim();
+        let error = |e: String| format!("line {}: {e}", number + 1);
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            sections.push(new(header.trim()).map_err(error)?);
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| error(format!("expected key = value but got {line:?}")))?;
+        let section = sections
+            .last_mut()
+            .ok_or_else(|| error("expected a [section] before the first key".to_string()))?;
+        set(section, key.trim(), value.trim())
+            .map_err(|e| error(format!("{}: {e}", key.trim())))?;
+    }
+    Ok(sections)
+}
+
+pub(crate) fn parse_f32(value: &str) -> Result<f32, String> {
+    value.parse().map_err(|e| format!("{value:?}: {e}"))
+}
+
+fn parse_quaternion(value: &str) -> Result<Quaternion<f32>, String> {
+    let parts = value
+        .split(',')
+        .map(|part| parse_f32(part.trim()))
+        .collect::<Result<Vec<_>, _>>()?;
+    match parts[..] {
+        [s, x, y, z] => Ok(Quaternion::new(s, x, y, z)),
+        _ => Err(format!("expected 4 numbers but got {}", parts.len())),
+    }
+}
+
+fn write_quaternion(q: Quaternion<f32>) -> String {
+    format!("{},{},{},{}", q.s, q.v.x, q.v.y, q.v.z)
+}
+
+/// Bookmarks by slot number.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Bookmarks {
+    slots: BTreeMap<u8, Bookmark>,
+}
+
+impl Bookmarks {
+    pub fn get(&self, slot: u8) -> Option<&Bookmark> {
+        self.slots.get(&slot)
+    }
+
+    /// Replaces whatever was in `slot`.
+    pub fn set(&mut self, slot: u8, bookmark: Bookmark) {
+        self.slots.insert(slot, bookmark);
+    }
+
+    /// Reads the bookmarks in `path`, or none if there's no file there yet.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        match fs::read_to_string(path) {
+            Ok(text) => text.parse().map_err(|e| format!("{}: {e}", path.display())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(format!("{}: {e}", path.display())),
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+}
+
+impl fmt::Display for Bookmarks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (slot, bookmark)) in self.slots.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            writeln!(f, "[{slot}]")?;
+            write_camera(f, &bookmark.camera)?;
+            writeln!(f, "speed = {}", bookmark.speed)?;
+            for (key, value) in bookmark.params.entries() {
+                writeln!(f, "{key} = {value}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses the format `Display` writes.
+impl FromStr for Bookmarks {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let slots = parse_sections(
+            s,
+            |slot| {
+                let slot = slot.parse().map_err(|e| format!("slot {slot:?}: {e}"))?;
+                Ok((slot, Bookmark::default()))
+            },
+            |(_, bookmark), key, value| bookmark.set(key, value),
+        )?;
+        Ok(Self {
+            slots: slots.into_iter().collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Shading;
+
+    #[test]
+    fn bookmarks_round_trip_through_text() {
+        let camera = Camera::new([0.1, -0.25, -1.5], 0.3, -0.2)
+            .with_roll(0.1)
+            .with_w_angles([0.2, 0.0, -0.4])
+            .with_fov(1.2);
+        let mut bookmarks = Bookmarks::default();
+        bookmarks.set(
+            3,
+            Bookmark {
+                camera,
+                speed: 2.5,
+                params: Params {
+                    shading: Shading::Iterations,
+                    slice_w: 0.2,
+                    ..Params::default()
+                },
+            },
+        );
+        bookmarks.set(1, Bookmark::default());
+
+        let parsed: Bookmarks = bookmarks.to_string().parse().unwrap();
+        let [original, parsed] = [&bookmarks, &parsed].map(|b| b.get(3).unwrap());
+        // The field of view goes through degrees
+        assert!((original.camera.fov - parsed.camera.fov).abs() < 1e-6);
+        assert_eq!(original.camera.position(), parsed.camera.position());
+        assert_eq!(
+            original.camera.orientation().normalize(),
+            parsed.camera.orientation()
+        );
+        assert_eq!(original.speed, parsed.speed);
+        assert_eq!(original.params, parsed.params);
+        assert!(bookmarks.get(1).is_some());
+        assert!(bookmarks.get(2).is_none());
+    }
+
+    #[test]
+    fn missing_params_keep_their_defaults() {
+        let bookmarks: Bookmarks = "[1]\npos = 1,2,3\nspeed = 4\n".parse().unwrap();
+        let bookmark = bookmarks.get(1).unwrap();
+
+        assert_eq!(bookmark.camera.position(), &Position::from([1.0, 2.0, 3.0]));
+        assert_eq!(bookmark.speed, 4.0);
+        assert_eq!(bookmark.params, Params::default());
+    }
+
+    #[test]
+    fn unknown_keys_are_errors() {
+        let error = "[1]\nspeed = 1\ncolour = red\n"
+            .parse::<Bookmarks>()
+            .unwrap_err();
+        assert!(error.starts_with("line 3"), "{error}");
+    }
+}
@@ -1,19 +1,204 @@
-use winit::event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent};
+use std::time::Duration;
 
+use cgmath::{InnerSpace, Matrix, Matrix4, Quaternion, Rad, Rotation3, Vector3, Vector4};
+use winit::event::{ElementState, KeyboardInput, MouseScrollDelta, VirtualKeyCode, WindowEvent};
+
+use crate::{df64::Df64, position::Position, rotation::Rotation4};
+
+/// How far the camera turns per pixel the mouse moves, in radians, before
+/// `;` and `'` change it.
+pub const DEFAULT_MOUSE_SENSITIVITY: f32 = 0.003;
+
+// Touchpads scroll in pixels rather than lines, this many make a line
+const PIXELS_PER_LINE: f32 = 20.0;
+
+// How much every line scrolled up speeds the camera up
+const SCROLL_MULTIPLIER: f32 = 1.25;
+
+// The longest frame `update_camera` moves the camera for
+const MAX_DT: Duration = Duration::from_secs(1);
+
+// With inertia, how quickly the camera gets up to speed, in speeds per
+// second, and how quickly it slows down again, per second. Being equal the
+// top speed is the speed
+const ACCELERATION: f32 = 6.0;
+const DAMPING: f32 = 6.0;
+
+// With collisions, how much of the distance to the surface the camera may
+// cover in one update
+const COLLISION_MARGIN: f32 = 0.5;
+
+// The range the speed and the orbit distance are kept in, so no amount of
+// speeding up or dollying takes them to 0 or infinity
+const MIN_SCALE: f32 = 1e-30;
+const MAX_SCALE: f32 = 1e30;
+
+// How long `CameraController::fly_to` takes
+const FLIGHT_TIME: Duration = Duration::from_secs(1);
+
+// The camera size multiplier
+// The smaller the better
+// Until floating point prec errors
+pub(crate) const SCALE: f32 = 0.0001;
+
+/// Horizontal field of view that `Camera::new` starts with, in radians.
+pub const DEFAULT_FOV: f32 = std::f32::consts::FRAC_PI_2;
+
+/// Where the camera is in 4D and where it's looking.
+///
+/// The camera sees the 3D hyperplane through its position that its right,
+/// up and forward span. Its fourth axis, which it can move along and turn
+/// towards, is the one that hyperplane leaves out.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Camera {
+    pos: Position,
+    // Turns the camera's local frame, x right, y up, z forward and w the
+    // axis it doesn't see, into world space
+    orientation: Rotation4,
+    // Horizontal, in radians
+    pub(crate) fov: f32,
+    // Width / height of the image being rendered
+    pub(crate) aspect: f32,
+}
+
+impl Camera {
+    /// A camera at `pos`, turned by `yaw` around the y axis and then
+    /// tilted by `pitch`, both in radians.
+    pub fn new(pos: impl Into<Position>, yaw: f32, pitch: f32) -> Self {
+        Self {
+            pos: pos.into(),
+            orientation: Rotation4::from(
+                Quaternion::from_angle_y(Rad(-yaw)) * Quaternion::from_angle_x(Rad(-pitch)),
+            ),
+            fov: DEFAULT_FOV,
+            aspect: 16.0 / 9.0,
+        }
+    }
+
+    /// The position in arbitrary precision. The shader gets it rounded to
+    /// double precision, or relative to it with `Precision::Perturbation`.
+    pub fn position(&self) -> &Position {
+        &self.pos
+    }
+
+    pub fn set_position(&mut self, pos: impl Into<Position>) {
+        self.pos = pos.into();
+    }
+
+    /// Which way the camera is facing, as the rotation that turns its local
+    /// frame (x right, y up, z forward and w) into world space.
+    pub fn orientation(&self) -> Rotation4 {
+        self.orientation
+    }
+
+    pub fn set_orientation(&mut self, orientation: Rotation4) {
+        self.orientation = orientation.normalize();
+    }
+
+    /// Rolls the camera clockwise around the way it's looking, in radians.
+    pub fn with_roll(mut self, roll: f32) -> Self {
+        self.rotate_local(Quaternion::from_angle_z(Rad(-roll)).into());
+        self
+    }
+
+    /// Turns the camera's right, up and forward towards its w axis by the
+    /// respective angles in radians, one after the other.
+    pub fn with_w_angles(mut self, angles: [f32; 3]) -> Self {
+        for (axis, angle) in angles.into_iter().enumerate() {
+            self.rotate_local(Rotation4::towards_w(axis, angle));
+        }
+        self
+    }
+
+    /// Applies `rotation` in the camera's own frame, so turning left is
+    /// always around the camera's up, whichever way that points.
+    pub(crate) fn rotate_local(&mut self, rotation: Rotation4) {
+        // Renormalized so rounding errors don't pile up frame after frame
+        self.orientation = (self.orientation * rotation).normalize();
+    }
+
+    /// The orientation as the column major matrix the shader gets, whose
+    /// columns are the camera's axes.
+    pub(crate) fn rot(&self) -> [[f32; 4]; 4] {
+        Matrix4::from(self.orientation).into()
+    }
+
+    /// The camera's local axis `axis` in world space.
+    pub(crate) fn axis(&self, axis: usize) -> Vector4<f32> {
+        self.rot()[axis].into()
+    }
+
+    /// Sets the horizontal field of view, in radians.
+    pub fn with_fov(mut self, fov: f32) -> Self {
+        self.fov = fov;
+        self
+    }
+
+    /// Matches the projection to an image of `width` by `height` pixels.
+    pub(crate) fn set_aspect(&mut self, width: u32, height: u32) {
+        self.aspect = width as f32 / height as f32;
+    }
+
+    /// Same as `vs_main`: the point on the near plane at `screen_pos`,
+    /// relative to the camera position and before it gets rotated.
+    pub(crate) fn local_pos(&self, screen_pos: [f32; 2]) -> Vector3<f32> {
+        let half_width = (self.fov / 2.0).tan();
+        Vector3::new(
+            2.0 * SCALE * screen_pos[0] * half_width,
+            2.0 * SCALE * screen_pos[1] * half_width / self.aspect,
+            2.0 * SCALE,
+        )
+    }
+
+    /// Same as `vs_main`: the point on the near plane at `screen_pos`,
+    /// relative to the camera position.
+    pub(crate) fn real_pos(&self, screen_pos: [f32; 2]) -> Vector4<f32> {
+        // Same as `cam.rot * vec4(real_pos, 0.0)`
+        Matrix4::from(self.rot()) * self.local_pos(screen_pos).extend(0.0)
+    }
+}
+
+// Must be the same as shader.wgsl
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, Default)]
-pub struct Camera {
-    pos: [f32; 3],
-    _padding: u32,
+pub(crate) struct CameraUniform {
+    // Rounded to f32, `pos_lo` has what's left of the double precision
+    // position
+    pos: [f32; 4],
     rot: [[f32; 4]; 4],
+    fov: f32,
+    aspect: f32,
+    _padding: [u32; 2],
+    pos_lo: [f32; 4],
+}
+
+impl From<&Camera> for CameraUniform {
+    fn from(camera: &Camera) -> Self {
+        let pos = camera.pos.to_f64().map(Df64::from);
+
+        Self {
+            pos: pos.map(|x| x.hi),
+            rot: camera.rot(),
+            fov: camera.fov,
+            aspect: camera.aspect,
+            pos_lo: pos.map(|x| x.lo),
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self::new([0.0; 3], 0.0, 0.0)
+    }
 }
 
 #[derive(Default)]
 pub struct CameraController {
+    // Per second
     speed: f32,
     rotation_speed: f32,
     speed_multiplier: f32,
-    smi: f32, // speed multiplier inverse
     is_forward_pressed: bool,
     is_backward_pressed: bool,
     is_left_pressed: bool,
@@ -24,36 +209,166 @@ pub struct CameraController {
     is_yaw_right: bool,
     is_pitch_up: bool,
     is_pitch_down: bool,
+    is_roll_left: bool,
+    is_roll_right: bool,
     is_scale_up: bool,
     is_scale_down: bool,
-    yaw: f32,
-    pitch: f32,
+    // Ctrl, which turns the turning keys, the mouse and E and Q towards w
+    is_w_held: bool,
+    mouse_sensitivity: f32,
+    invert_mouse_y: bool,
+    // Mouse movement and scrolling since the last `update_camera`
+    mouse_delta: [f32; 2],
+    scroll_lines: f32,
+    // Whether the camera speeds up and coasts to a stop instead of moving
+    // only while keys are held
+    inertia: bool,
+    // In speeds
+    velocity: [f32; 4],
+    // Whether `speed` is a multiple of the distance to the surface, so the
+    // camera slows down on its own on the way in
+    auto_speed: bool,
+    // Whether the camera stops short of the surface instead of flying
+    // through it
+    collisions: bool,
+    orbit: Option<Orbit>,
+    flight: Option<Flight>,
 }
 
-fn pos_updated(
-    camera: &mut Camera,
-    is_backward: bool,
-    index: usize,
-    rotated: &[[f32; 3]],
-    speed: f32,
-) {
-    let speed = if is_backward { speed * -1.0 } else { speed };
-    camera.pos[0] += speed * rotated[index][0];
-    camera.pos[1] += speed * rotated[index][1];
-    camera.pos[2] += speed * rotated[index][2];
+/// A point the camera circles around while looking at it.
+#[derive(Clone, Debug)]
+struct Orbit {
+    pivot: Position,
+    distance: f64,
+}
+
+impl Orbit {
+    /// Moves the camera back from the pivot along the way it's looking.
+    fn place(&self, camera: &mut Camera) {
+        let forward: [f32; 4] = camera.axis(2).into();
+        camera.pos = self.pivot.clone();
+        camera
+            .pos
+            .translate(forward.map(|x| -x as f64 * self.distance));
+    }
+}
+
+/// A smooth move from one view to another, which the keys can't interrupt.
+#[derive(Clone, Debug)]
+struct Flight {
+    from: Camera,
+    to: Camera,
+    elapsed: Duration,
+}
+
+impl Flight {
+    /// Moves the camera however far along it should be by now, easing in
+    /// and out, and exactly to the end once it's done.
+    fn place(&self, camera: &mut Camera) {
+        if self.is_done() {
+            camera.pos = self.to.pos.clone();
+            camera.orientation = self.to.orientation;
+            camera.fov = self.to.fov;
+            return;
+        }
+
+        let t = self.elapsed.as_secs_f32() / FLIGHT_TIME.as_secs_f32();
+        let t = t * t * (3.0 - 2.0 * t);
+
+        // The offset is rounded to f64, which deep zooms need more digits
+        // than, so this goes from whichever end is closer
+        let offset = self.from.pos.offset_to(&self.to.pos);
+        let (start, along) = if t < 0.5 {
+            (&self.from.pos, t)
+        } else {
+            (&self.to.pos, t - 1.0)
+        };
+        camera.pos = start.clone();
+        camera.pos.translate(offset.map(|x| x * along as f64));
+        camera.orientation = self.from.orientation.slerp(self.to.orientation, t);
+        camera.fov = self.from.fov + (self.to.fov - self.from.fov) * t;
+    }
+
+    fn is_done(&self) -> bool {
+        self.elapsed >= FLIGHT_TIME
+    }
 }
 
 impl CameraController {
+    /// `speed` is in distances to the surface and `rotation_speed` in
+    /// radians per second, and holding 0 multiplies the speed by
+    /// `speed_multiplier` every second. With automatic speed turned off with
+    /// O, `speed` is in units per second instead.
     pub fn new(speed: f32, rotation_speed: f32, speed_multiplier: f32) -> Self {
         Self {
             rotation_speed,
             speed,
             speed_multiplier,
-            smi: 1.0 / speed_multiplier,
+            auto_speed: true,
+            mouse_sensitivity: DEFAULT_MOUSE_SENSITIVITY,
             ..Default::default()
         }
     }
 
+    /// Switches to orbiting around `pivot`, turning the camera to look at it
+    /// from where it is.
+    pub fn orbit_around(&mut self, camera: &mut Camera, pivot: Position) {
+        let offset = Vector4::from(camera.position().offset_to(&pivot));
+        let distance = offset.magnitude();
+
+        // Turns within the hyperplane the camera sees, which a pivot picked
+        // on the screen is always in
+        let local = Matrix4::from(camera.rot()).transpose() * offset.map(|x| x as f32);
+        let direction = local.truncate();
+        if direction.magnitude() > 0.0 {
+            let turn = Quaternion::from_arc(Vector3::unit_z(), direction.normalize(), None);
+            camera.rotate_local(turn.into());
+        }
+
+        log::info!("orbiting around {pivot} at {distance:e}");
+        self.orbit = Some(Orbit { pivot, distance });
+    }
+
+    /// Goes back to flying freely, from wherever the orbit left the camera.
+    pub fn stop_orbiting(&mut self) {
+        if self.orbit.take().is_some() {
+            log::info!("flying freely");
+        }
+    }
+
+    pub fn is_orbiting(&self) -> bool {
+        self.orbit.is_some()
+    }
+
+    /// Flies the camera over to the position, orientation and field of view
+    /// of `to`, flying freely once it gets there.
+    pub fn fly_to(&mut self, camera: &Camera, to: &Camera) {
+        self.stop_orbiting();
+        self.velocity = [0.0; 4];
+        self.flight = Some(Flight {
+            from: camera.clone(),
+            to: to.clone(),
+            elapsed: Duration::ZERO,
+        });
+    }
+
+    /// In distances to the surface or units per second, see `new`.
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.clamp(MIN_SCALE, MAX_SCALE);
+    }
+
+    /// Turns the camera by raw mouse movement, in pixels. Only meant to be
+    /// called while the cursor is grabbed, so looking around doesn't also
+    /// move it out of the window.
+    pub fn process_mouse_motion(&mut self, dx: f64, dy: f64) {
+        self.mouse_delta[0] += dx as f32;
+        self.mouse_delta[1] += dy as f32;
+    }
+
     pub fn process_events(&mut self, event: &WindowEvent) -> bool {
         match event {
             WindowEvent::KeyboardInput {
@@ -107,6 +422,18 @@ impl CameraController {
                         self.is_yaw_right = is_pressed;
                         true
                     }
+                    VirtualKeyCode::Z => {
+                        self.is_roll_left = is_pressed;
+                        true
+                    }
+                    VirtualKeyCode::X => {
+                        self.is_roll_right = is_pressed;
+                        true
+                    }
+                    VirtualKeyCode::LControl | VirtualKeyCode::RControl => {
+                        self.is_w_held = is_pressed;
+                        true
+                    }
                     VirtualKeyCode::Key0 => {
                         self.is_scale_up = is_pressed;
                         true
@@ -115,78 +442,435 @@ impl CameraController {
                         self.is_scale_down = is_pressed;
                         true
                     }
+                    VirtualKeyCode::M => {
+                        if is_pressed {
+                            self.inertia = !self.inertia;
+                            log::info!("inertia: {}", self.inertia);
+                        }
+                        true
+                    }
+                    VirtualKeyCode::O => {
+                        if is_pressed {
+                            self.auto_speed = !self.auto_speed;
+                            log::info!("automatic speed: {}", self.auto_speed);
+                        }
+                        true
+                    }
+                    VirtualKeyCode::L => {
+                        if is_pressed {
+                            self.collisions = !self.collisions;
+                            log::info!("collisions: {}", self.collisions);
+                        }
+                        true
+                    }
+                    VirtualKeyCode::I => {
+                        if is_pressed {
+                            self.invert_mouse_y = !self.invert_mouse_y;
+                            log::info!("invert mouse y: {}", self.invert_mouse_y);
+                        }
+                        true
+                    }
+                    VirtualKeyCode::Semicolon | VirtualKeyCode::Apostrophe => {
+                        if is_pressed {
+                            if *keycode == VirtualKeyCode::Semicolon {
+                                self.mouse_sensitivity /= 1.25;
+                            } else {
+                                self.mouse_sensitivity *= 1.25;
+                            }
+                            log::info!("mouse sensitivity: {:.5}", self.mouse_sensitivity);
+                        }
+                        true
+                    }
                     _ => false,
                 }
             }
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.scroll_lines += match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(pos) => pos.y as f32 / PIXELS_PER_LINE,
+                };
+                true
+            }
             _ => false,
         }
     }
 
-    pub fn update_camera(&mut self, camera: &mut Camera) {
-        println!("{}", self.speed);
+    /// Moves and turns the camera by how long the keys have been held for
+    /// since the last update, `dt`, so it doesn't depend on the frame rate.
+    ///
+    /// `distance` is the distance estimate at the camera, which the speed
+    /// gets scaled by and which collisions keep the camera from crossing.
+    pub fn update_camera(&mut self, camera: &mut Camera, dt: Duration, distance: f32) {
+        // So a stall, like dragging the window around, doesn't fling the
+        // camera off into the distance
+        let dt = dt.min(MAX_DT);
 
-        let (pitch_sin, pitch_cos) = self.pitch.sin_cos();
-        let (yaw_sin, yaw_cos) = self.yaw.sin_cos();
+        if let Some(flight) = &mut self.flight {
+            flight.elapsed += dt;
+            flight.place(camera);
+            if flight.is_done() {
+                self.flight = None;
+            }
+            // Nothing the mouse did on the way carries over
+            self.mouse_delta = [0.0; 2];
+            self.scroll_lines = 0.0;
+            return;
+        }
+        let dt = dt.as_secs_f32();
 
-        camera.rot = [
-            [yaw_cos, 0.0, yaw_sin, 0.0],
-            [pitch_sin * yaw_sin, pitch_cos, -pitch_sin * yaw_cos, 0.0],
-            [pitch_cos * -yaw_sin, pitch_sin, pitch_cos * yaw_cos, 0.0],
-            [0.0, 0.0, 0.0, 1.0],
-        ];
+        // The camera's right, up, forward and w axes
+        let rotated = [0, 1, 2, 3].map(|axis| camera.axis(axis));
 
-        let rotated = [
-            [yaw_cos, 0.0, yaw_sin],
-            [camera.rot[1][0], pitch_cos, camera.rot[1][2]],
-            [camera.rot[2][0], pitch_sin, camera.rot[2][2]],
-        ];
+        let [dx, dy] = std::mem::take(&mut self.mouse_delta);
+        let dy = if self.invert_mouse_y { -dy } else { dy };
+        let turn = self.rotation_speed * dt;
+        // Same directions as the keys: right turns right and up looks up
+        let yaw = -dx * self.mouse_sensitivity + turn * held(self.is_yaw_left, self.is_yaw_right);
+        let pitch =
+            -dy * self.mouse_sensitivity + turn * held(self.is_pitch_up, self.is_pitch_down);
+        let roll = turn * held(self.is_roll_right, self.is_roll_left);
 
-        if self.is_left_pressed {
-            pos_updated(camera, true, 0, &rotated, self.speed)
-        }
+        // Around the camera's own axes, so there's no gimbal lock looking
+        // straight up or down. With Ctrl held the same keys turn right, up
+        // and forward towards w instead
+        let rotation = if self.is_w_held {
+            Rotation4::towards_w(0, -yaw)
+                * Rotation4::towards_w(1, pitch)
+                * Rotation4::towards_w(2, roll)
+        } else {
+            Rotation4::from(
+                Quaternion::from_angle_y(Rad(-yaw))
+                    * Quaternion::from_angle_x(Rad(-pitch))
+                    * Quaternion::from_angle_z(Rad(-roll)),
+            )
+        };
+        // E and Q move along w instead of up with Ctrl held
+        let up = if self.is_w_held {
+            rotated[3]
+        } else {
+            rotated[1]
+        };
+        let scroll_lines = std::mem::take(&mut self.scroll_lines);
 
-        if self.is_right_pressed {
-            pos_updated(camera, false, 0, &rotated, self.speed)
-        }
+        if let Some(orbit) = &mut self.orbit {
+            // Panning moves the pivot along, at a speed that suits how far
+            // away it is
+            let pan = rotated[0] * held(self.is_right_pressed, self.is_left_pressed)
+                + up * held(self.is_up_pressed, self.is_down_pressed);
+            let pan: [f32; 4] = (pan * self.speed * dt).into();
+            orbit
+                .pivot
+                .translate(pan.map(|x| x as f64 * orbit.distance));
 
-        if self.is_backward_pressed {
-            pos_updated(camera, true, 2, &rotated, self.speed)
-        }
+            // Dollying scales the distance, so the camera never reaches the
+            // pivot however long it's held
+            let dolly = self
+                .speed_multiplier
+                .powf(dt * held(self.is_forward_pressed, self.is_backward_pressed))
+                * SCROLL_MULTIPLIER.powf(scroll_lines);
+            orbit.distance =
+                (orbit.distance / dolly as f64).clamp(MIN_SCALE as f64, MAX_SCALE as f64);
 
-        if self.is_forward_pressed {
-            pos_updated(camera, false, 2, &rotated, self.speed)
-        }
+            self.velocity = [0.0; 4];
+            camera.rotate_local(rotation);
+            orbit.place(camera);
+        } else {
+            let direction = rotated[0] * held(self.is_right_pressed, self.is_left_pressed)
+                + up * held(self.is_up_pressed, self.is_down_pressed)
+                + rotated[2] * held(self.is_forward_pressed, self.is_backward_pressed);
 
-        if self.is_down_pressed {
-            pos_updated(camera, true, 1, &rotated, self.speed)
-        }
+            // Inside the set there's no estimate, but the camera still has to be
+            // able to get back out
+            let distance = (distance > 0.0 && distance.is_finite()).then_some(distance);
+            let speed = match distance {
+                Some(distance) if self.auto_speed => self.speed * distance,
+                _ => self.speed,
+            };
 
-        if self.is_up_pressed {
-            pos_updated(camera, false, 1, &rotated, self.speed)
-        }
+            let velocity = Vector4::from(self.velocity);
+            let (velocity, offset) = if self.inertia {
+                // Integrates `v' = a * direction - damping * v` exactly, rather
+                // than a step per frame, so the frame rate doesn't change the path
+                let decay = (-DAMPING * dt).exp();
+                let terminal = direction * (ACCELERATION / DAMPING);
+                (
+                    terminal + (velocity - terminal) * decay,
+                    terminal * dt + (velocity - terminal) * ((1.0 - decay) / DAMPING),
+                )
+            } else {
+                (direction, direction * dt)
+            };
+            // The velocity is in terms of the speed, so momentum doesn't carry
+            // the camera too far once the speed drops near the surface
+            self.velocity = velocity.into();
+            let mut offset = offset * speed;
 
-        if self.is_pitch_down {
-            self.pitch -= self.rotation_speed
-        }
+            if let Some(distance) = distance.filter(|_| self.collisions) {
+                // Nothing is closer than the distance estimate, so only ever
+                // covering part of it never reaches the surface
+                let max_len = COLLISION_MARGIN * distance;
+                if offset.magnitude() > max_len {
+                    offset = offset.normalize_to(max_len);
+                }
+            }
+
+            // Moves in arbitrary precision, so tiny steps still count however far
+            // the camera has zoomed in
+            let offset: [f32; 4] = offset.into();
+            camera.pos.translate(offset.map(f64::from));
 
-        if self.is_pitch_up {
-            self.pitch += self.rotation_speed
+            camera.rotate_local(rotation);
         }
 
-        if self.is_yaw_right {
-            self.yaw -= self.rotation_speed
+        // Scrolling dollies instead while orbiting
+        let scroll_lines = if self.orbit.is_some() {
+            0.0
+        } else {
+            scroll_lines
+        };
+        let scale = self
+            .speed_multiplier
+            .powf(dt * held(self.is_scale_up, self.is_scale_down))
+            * SCROLL_MULTIPLIER.powf(scroll_lines);
+        self.speed = (self.speed * scale).clamp(MIN_SCALE, MAX_SCALE);
+    }
+}
+
+/// 1 if only `positive` is held, -1 if only `negative` is and 0 otherwise.
+fn held(positive: bool, negative: bool) -> f32 {
+    positive as i32 as f32 - negative as i32 as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: [f32; 4], b: [f32; 4]) {
+        let diff = Vector4::from(a) - Vector4::from(b);
+        assert!(diff.magnitude() < 1e-5, "{a:?} != {b:?}");
+    }
+
+    fn axis(camera: &Camera, axis: usize) -> [f32; 4] {
+        camera.axis(axis).into()
+    }
+
+    /// Holds keys for half a second, over `frames` frames.
+    fn hold(frames: u32, press: impl Fn(&mut CameraController)) -> (Camera, CameraController) {
+        let mut camera = Camera::new([0.0, 0.0, -3.0], 0.3, 0.2);
+        let mut controller = CameraController::new(1.0, 1.0, 4.0);
+        press(&mut controller);
+        for _ in 0..frames {
+            controller.update_camera(&mut camera, Duration::from_millis(500) / frames, 1.0);
         }
+        (camera, controller)
+    }
 
-        if self.is_yaw_left {
-            self.yaw += self.rotation_speed
+    #[test]
+    fn motion_doesnt_depend_on_the_frame_rate() {
+        for inertia in [false, true] {
+            let press = |controller: &mut CameraController| {
+                controller.inertia = inertia;
+                controller.is_forward_pressed = true;
+                controller.is_up_pressed = true;
+            };
+            let (slow, _) = hold(2, press);
+            let (fast, _) = hold(60, press);
+            assert_close(slow.position().to_f32(), fast.position().to_f32());
         }
 
-        if self.is_scale_up {
-            self.speed *= self.speed_multiplier
+        let (slow, _) = hold(2, |controller| controller.is_yaw_left = true);
+        let (fast, _) = hold(60, |controller| controller.is_yaw_left = true);
+        assert_close(axis(&slow, 2), axis(&fast, 2));
+
+        let (_, slow) = hold(2, |controller| controller.is_scale_up = true);
+        let (_, fast) = hold(60, |controller| controller.is_scale_up = true);
+        assert!((slow.speed - 2.0).abs() < 1e-5);
+        assert!((fast.speed - 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn speeding_up_forever_stays_finite() {
+        let mut camera = Camera::default();
+        let mut controller = CameraController::new(1.0, 1.0, 4.0);
+        controller.scroll_lines = 1000.0;
+        controller.is_scale_up = true;
+        for _ in 0..100 {
+            controller.update_camera(&mut camera, Duration::from_secs(10), 1.0);
         }
+        assert!(controller.speed.is_finite());
+
+        // With nothing held the offset is 0 times the speed
+        controller.update_camera(&mut camera, Duration::from_secs(10), 1.0);
+        controller.is_forward_pressed = true;
+        controller.update_camera(&mut camera, Duration::from_secs(10), 1.0);
+        assert!(camera.position().to_f64().iter().all(|x| x.is_finite()));
+    }
+
+    #[test]
+    fn inertia_coasts_to_a_stop() {
+        let (mut camera, mut controller) = hold(30, |controller| {
+            controller.inertia = true;
+            controller.is_forward_pressed = true;
+        });
+        controller.is_forward_pressed = false;
+
+        let before = camera.position().to_f32();
+        controller.update_camera(&mut camera, Duration::from_millis(100), 1.0);
+        let coasted = camera.position().to_f32();
+        assert_ne!(before, coasted);
+
+        controller.update_camera(&mut camera, Duration::from_secs(1), 1.0);
+        controller.update_camera(&mut camera, Duration::from_secs(1), 1.0);
+        let stopped = camera.position().to_f32();
+        controller.update_camera(&mut camera, Duration::from_millis(100), 1.0);
+        assert_close(camera.position().to_f32(), stopped);
+    }
+
+    #[test]
+    fn auto_speed_slows_down_near_the_surface() {
+        let mut camera = Camera::default();
+        let mut controller = CameraController::new(1.0, 1.0, 4.0);
+        controller.is_forward_pressed = true;
+
+        controller.update_camera(&mut camera, Duration::from_secs(1), 0.001);
+        assert_close(camera.position().to_f32(), [0.0, 0.0, 0.001, 0.0]);
+
+        controller.auto_speed = false;
+        controller.update_camera(&mut camera, Duration::from_secs(1), 0.001);
+        assert_close(camera.position().to_f32(), [0.0, 0.0, 1.001, 0.0]);
+    }
+
+    #[test]
+    fn collisions_stop_short_of_the_surface() {
+        let mut camera = Camera::default();
+        let mut controller = CameraController::new(100.0, 1.0, 4.0);
+        controller.collisions = true;
+        controller.is_forward_pressed = true;
+
+        controller.update_camera(&mut camera, Duration::from_secs(1), 0.1);
+        assert!(camera.position().to_f32()[2] < 0.1);
+
+        // Inside the set there's no estimate to stop at
+        controller.update_camera(&mut camera, Duration::from_millis(100), 0.0);
+        assert!(camera.position().to_f32()[2] > 1.0);
+    }
+
+    #[test]
+    fn orbiting_turns_to_the_pivot_and_keeps_its_distance() {
+        let mut camera = Camera::new([0.0, 0.0, -3.0], 0.3, 0.2);
+        let mut controller = CameraController::new(1.0, 1.0, 4.0);
+        controller.orbit_around(&mut camera, [0.5, 0.0, -1.0].into());
 
-        if self.is_scale_down {
-            self.speed *= self.smi
+        // Looking at the pivot from where it was
+        assert_close(camera.position().to_f32(), [0.0, 0.0, -3.0, 0.0]);
+        let to_pivot = Vector4::new(0.5f32, 0.0, 2.0, 0.0).normalize();
+        assert_close(axis(&camera, 2), to_pivot.into());
+
+        controller.is_yaw_left = true;
+        controller.is_pitch_up = true;
+        controller.update_camera(&mut camera, Duration::from_millis(500), 1.0);
+        let offset = Vector4::from(camera.position().offset_to(&[0.5f64, 0.0, -1.0].into()));
+        assert!((offset.magnitude() - 4.25f64.sqrt()).abs() < 1e-5);
+        assert_close(
+            axis(&camera, 2),
+            offset.normalize().map(|x| x as f32).into(),
+        );
+
+        // Dollying in gets closer without ever getting there
+        controller.is_yaw_left = false;
+        controller.is_pitch_up = false;
+        controller.is_forward_pressed = true;
+        controller.update_camera(&mut camera, Duration::from_secs(1), 1.0);
+        let offset = Vector4::from(camera.position().offset_to(&[0.5f64, 0.0, -1.0].into()));
+        assert!((offset.magnitude() - 4.25f64.sqrt() / 4.0).abs() < 1e-5);
+
+        controller.stop_orbiting();
+        assert!(!controller.is_orbiting());
+    }
+
+    #[test]
+    fn new_turns_by_yaw_then_pitch() {
+        let (yaw, pitch) = (0.7f32, -0.4f32);
+        let camera = Camera::new([0.0; 3], yaw, pitch);
+
+        assert_close(axis(&camera, 0), [yaw.cos(), 0.0, yaw.sin(), 0.0]);
+        assert_close(
+            axis(&camera, 2),
+            [
+                -pitch.cos() * yaw.sin(),
+                pitch.sin(),
+                pitch.cos() * yaw.cos(),
+                0.0,
+            ],
+        );
+        assert_close(axis(&camera, 3), [0.0, 0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn roll_turns_up_towards_right() {
+        let camera = Camera::new([0.0; 3], 0.3, 0.2);
+        let rolled = camera.clone().with_roll(std::f32::consts::FRAC_PI_2);
+
+        assert_close(axis(&rolled, 2), axis(&camera, 2));
+        assert_close(axis(&rolled, 1), axis(&camera, 0));
+    }
+
+    #[test]
+    fn turning_straight_up_and_over_keeps_going() {
+        let mut camera = Camera::default();
+        for _ in 0..4 {
+            camera.rotate_local(Quaternion::from_angle_x(Rad(-std::f32::consts::FRAC_PI_2)).into());
         }
+
+        assert_close(axis(&camera, 2), [0.0, 0.0, 1.0, 0.0]);
+        let orientation = camera.orientation();
+        assert!((orientation.left.magnitude() - 1.0).abs() < 1e-6);
+        assert!((orientation.right.magnitude() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn ctrl_moves_and_turns_towards_w() {
+        let mut camera = Camera::default();
+        let mut controller = CameraController::new(1.0, std::f32::consts::PI, 4.0);
+        controller.auto_speed = false;
+        controller.is_w_held = true;
+        controller.is_up_pressed = true;
+
+        controller.update_camera(&mut camera, Duration::from_millis(500), 1.0);
+        assert_close(camera.position().to_f32(), [0.0, 0.0, 0.0, 0.5]);
+
+        controller.is_up_pressed = false;
+        controller.is_yaw_right = true;
+        controller.update_camera(&mut camera, Duration::from_millis(500), 1.0);
+        // A quarter turn of right towards w, which leaves up and forward be
+        assert_close(axis(&camera, 0), [0.0, 0.0, 0.0, 1.0]);
+        assert_close(axis(&camera, 3), [-1.0, 0.0, 0.0, 0.0]);
+        assert_close(axis(&camera, 2), [0.0, 0.0, 1.0, 0.0]);
+
+        // Now moving right is moving along w
+        controller.is_w_held = false;
+        controller.is_yaw_right = false;
+        controller.is_right_pressed = true;
+        controller.update_camera(&mut camera, Duration::from_millis(500), 1.0);
+        assert_close(camera.position().to_f32(), [0.0, 0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn flying_to_a_camera_lands_exactly_on_it() {
+        let mut camera = Camera::new([0.0, 0.0, -3.0], 0.3, 0.2);
+        let to = Camera::new([1.0, 2.0, -1.0], -0.5, 0.1)
+            .with_w_angles([0.0, 0.3, 0.0])
+            .with_fov(1.0);
+        let mut controller = CameraController::new(1.0, 1.0, 4.0);
+        controller.fly_to(&camera, &to);
+
+        // Keys held on the way don't move it off course
+        controller.is_forward_pressed = true;
+        controller.update_camera(&mut camera, FLIGHT_TIME / 2, 1.0);
+        assert_close(camera.position().to_f32(), [0.5, 1.0, -2.0, 0.0]);
+        assert!((camera.fov - (DEFAULT_FOV + 1.0) / 2.0).abs() < 1e-6);
+
+        controller.update_camera(&mut camera, FLIGHT_TIME / 2, 1.0);
+        assert_eq!(camera, to);
     }
 }
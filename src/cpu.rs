@@ -0,0 +1,861 @@
+//! A CPU port of `shader.wgsl`.
+//!
+//! Every function here mirrors the one with the same name in the shader,
+//! so this can render on machines without a GPU and act as the reference
+//! the GPU output is checked against. Keep the two in sync!
+
+use cgmath::{ElementWise, InnerSpace, Matrix4, SquareMatrix, Vector3, Vector4, Zero};
+use rayon::prelude::*;
+
+use crate::{
+    df64::Df64, flip::MAX_FLIPS, image::Image, perturbation::ReferenceOrbit, screen_point, Camera,
+    Derivative, Estimator, Params, Position, Precision, Quaternion, Shading,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Escape {
+    /// Estimated distance to the set
+    pub distance: f32,
+    /// How many iterations it took to escape
+    pub iterations: u32,
+    /// `ln(r)` of the escaped `z`. The escape potential is this over
+    /// `n^iterations`, see `gradient_estimate`
+    pub log_radius: f32,
+}
+
+/// How much `z^power` changes when `z` moves by `dz`, to first order.
+fn pow_derivative(z: Quaternion, dz: Quaternion, power: f32) -> Quaternion {
+    if power == 2.0 {
+        // Faster and more precise than going through polar form
+        z * dz + dz * z
+    } else {
+        z.powf_derivative(power, dz)
+    }
+}
+
+/// What the distance estimate tracks while iterating, see `Derivative`.
+/// Only one of them gets updated.
+#[derive(Copy, Clone)]
+struct Derivatives {
+    dz: Quaternion,
+    jacobian: Matrix4<f32>,
+}
+
+fn start_derivatives() -> Derivatives {
+    Derivatives {
+        dz: Quaternion::ONE,
+        jacobian: Matrix4::identity(),
+    }
+}
+
+fn flip_derivatives(derivatives: Derivatives, flip: Matrix4<f32>) -> Derivatives {
+    // The quaternion derivative ignores the flip
+    Derivatives {
+        dz: derivatives.dz,
+        jacobian: flip * derivatives.jacobian,
+    }
+}
+
+/// Takes the derivatives of `z` to those of `z^n + c`, where `dc` is the
+/// derivative of `c` with respect to `pos`.
+fn step_derivatives(
+    derivatives: Derivatives,
+    z: Quaternion,
+    dc: f32,
+    params: &Params,
+) -> Derivatives {
+    let power = params.power;
+    let mut result = derivatives;
+    match params.derivative {
+        Derivative::Jacobian => {
+            let column =
+                |column: Vector4<f32>| Vector4::from(pow_derivative(z, column.into(), power));
+            let jacobian = derivatives.jacobian;
+            result.jacobian = Matrix4::from_cols(
+                column(jacobian.x),
+                column(jacobian.y),
+                column(jacobian.z),
+                column(jacobian.w),
+            ) + Matrix4::from_scale(dc);
+        }
+        // d(z^n + c)/dpos = n z^(n-1) dz + dc/dpos. The derivative isn't
+        // really a quaternion product, but the norms match, which is all
+        // the distance estimate needs
+        Derivative::Quaternion if power == 2.0 => {
+            result.dz = 2.0 * (z * derivatives.dz) + dc.into();
+        }
+        Derivative::Quaternion => {
+            result.dz = power * (z.powf(power - 1.0) * derivatives.dz) + dc.into();
+        }
+    }
+    result
+}
+
+/// The `Escape` for a `z` that escaped after `iters` iterations.
+fn escape(z: Quaternion, derivatives: Derivatives, iters: u32, params: &Params) -> Escape {
+    // The Frobenius norm is never smaller than how much the Jacobian
+    // stretches any direction, so the estimate errs on the short side
+    let derivative = match params.derivative {
+        Derivative::Quaternion => derivatives.dz.norm(),
+        Derivative::Jacobian => {
+            let jacobian = derivatives.jacobian;
+            (jacobian.x.magnitude2()
+                + jacobian.y.magnitude2()
+                + jacobian.z.magnitude2()
+                + jacobian.w.magnitude2())
+            .sqrt()
+        }
+    };
+
+    let r = z.norm();
+    let distance = match params.estimator {
+        Estimator::Linear => 0.5 * r / derivative,
+        // The gradient estimator doesn't use this, see `de`
+        Estimator::Log | Estimator::Gradient => 0.5 * r.ln() * r / derivative,
+    };
+    Escape {
+        distance,
+        iterations: iters,
+        log_radius: r.ln(),
+    }
+}
+
+/// Runs the flipped quaternion mandelbrot iteration `z^n + c` for `pos`, or the
+/// Julia one if `params.julia` is set.
+pub fn mandelbrot(pos: Quaternion, params: &Params) -> Escape {
+    let mut iters = 0;
+    // dc is the derivative of c with respect to pos
+    let (c, dc) = if params.julia {
+        (params.julia_c.into(), 0.0)
+    } else {
+        (pos, 1.0)
+    };
+    let mut z = pos;
+    let mut derivatives = start_derivatives();
+    let flips = &params.flips[..params.flips.len().min(MAX_FLIPS)];
+    let mut flips_done = 0;
+
+    while iters <= params.max_iterations {
+        if !flips.is_empty() && params.flip_schedule.applies(iters) {
+            let flip = Matrix4::from(flips[flips_done % flips.len()].matrix);
+            z = (flip * Vector4::from(z)).into();
+            derivatives = flip_derivatives(derivatives, flip);
+            flips_done += 1;
+        }
+
+        derivatives = step_derivatives(derivatives, z, dc, params);
+
+        if params.power == 2.0 {
+            // Faster and more precise than going through polar form
+            z = z * z + c;
+        } else {
+            z = z.powf(params.power) + c;
+        }
+
+        let z2 = z.norm_squared();
+        if z2 > params.bailout {
+            break;
+        }
+
+        iters += 1;
+    }
+
+    escape(z, derivatives, iters, params)
+}
+
+/// Differences of `at` a step of `params.delta` forward and back along the
+/// camera's right, up and forward, where `at` takes the step. Rays never
+/// leave the hyperplane those span, so that's all of the gradient they
+/// need.
+fn differences(camera: &Camera, params: &Params, at: impl Fn(Vector4<f32>) -> f32) -> Vector3<f32> {
+    let [x, y, z] = [0, 1, 2].map(|axis| {
+        let step = camera.axis(axis) * params.delta;
+        at(step) - at(-step)
+    });
+    Vector3::new(x, y, z)
+}
+
+/// `cam.rot * vec4(v, 0.0)` in the shader, `v` from the camera's frame in
+/// world space.
+fn to_world(v: Vector3<f32>, camera: &Camera) -> Vector4<f32> {
+    Matrix4::from(camera.rot()) * v.extend(0.0)
+}
+
+/// The `Escape`s a step of `params.delta` forward and back along the
+/// camera's right, up and forward, like `differences`.
+fn gradient_samples(
+    camera: &Camera,
+    params: &Params,
+    at: impl Fn(Vector4<f32>) -> Escape,
+) -> [[Escape; 2]; 3] {
+    [0, 1, 2].map(|axis| {
+        let step = camera.axis(axis) * params.delta;
+        [at(step), at(-step)]
+    })
+}
+
+/// `0.5 G / |∇G|` from the escape potential `G = ln(r) / n^iterations` at
+/// `center` and the `samples` around it, see `gradient_samples`.
+/// `n^iterations` overflows f32 past 127 iterations at power 2, so every
+/// potential is instead scaled up by `n^k` for the fewest iterations `k` of
+/// any of them, which cancels out.
+fn gradient_estimate(center: Escape, samples: [[Escape; 2]; 3], params: &Params) -> f32 {
+    let scale = samples
+        .iter()
+        .flatten()
+        .fold(center.iterations, |scale, sample| {
+            scale.min(sample.iterations)
+        });
+    // scaled_potential in the shader
+    let scaled_potential =
+        |escape: Escape| escape.log_radius / params.power.powi((escape.iterations - scale) as i32);
+    let gradient = Vector3::from(
+        samples.map(|[forward, back]| scaled_potential(forward) - scaled_potential(back)),
+    ) / (2.0 * params.delta);
+    0.5 * scaled_potential(center) / gradient.magnitude()
+}
+
+/// `DE` in the shader.
+pub fn de(pos: Vector4<f32>, camera: &Camera, params: &Params) -> f32 {
+    let escape = mandelbrot(to_quat(pos, params), params);
+    let distance = if params.estimator == Estimator::Gradient {
+        let samples = gradient_samples(camera, params, |step| {
+            mandelbrot(to_quat(pos + step, params), params)
+        });
+        gradient_estimate(escape, samples, params)
+    } else {
+        escape.distance
+    };
+
+    params.fudge * distance
+}
+
+/// Surface normal at `pos`, from the gradient of the distance estimate.
+pub fn normals(pos: Vector4<f32>, camera: &Camera, params: &Params) -> Vector4<f32> {
+    let gradient = differences(camera, params, |step| de(pos + step, camera, params));
+    to_world(gradient, camera).normalize()
+}
+
+/// Lighting via Phong illumination of `p` with surface normal `n`, see the
+/// shader for details.
+pub fn phong(p: Vector4<f32>, n: Vector4<f32>, camera: &Camera, params: &Params) -> Vector3<f32> {
+    let [k_a, k_d, k_s, ambient_light] =
+        [params.k_a, params.k_d, params.k_s, params.ambient_light].map(Vector3::from);
+    let light_pos = Vector3::from(params.light_pos).extend(0.0);
+    let mut color = ambient_light.mul_element_wise(k_a);
+
+    let l = (light_pos - p).normalize();
+    let v = (cam_pos(camera) - p).normalize();
+    let r = reflect(-l, n).normalize();
+
+    let dot_ln = l.dot(n).clamp(0.0, 1.0);
+    let dot_rv = r.dot(v);
+
+    if dot_ln >= 0.0 && dot_rv < 0.0 {
+        color += params.light_intensity * k_d * dot_ln;
+    } else if dot_ln >= 0.0 {
+        color += params.light_intensity * (k_d * dot_ln + k_s * dot_rv.powf(params.shininess));
+    }
+
+    color
+}
+
+/// WGSL's `reflect`.
+fn reflect(i: Vector4<f32>, n: Vector4<f32>) -> Vector4<f32> {
+    i - 2.0 * n.dot(i) * n
+}
+
+/// Color of a ray that hit the set at `pos`.
+pub fn on_hit(pos: Vector4<f32>, camera: &Camera, params: &Params) -> Vector3<f32> {
+    match params.shading {
+        Shading::Normals => {
+            normals(pos, camera, params).truncate() * 0.5 + Vector3::new(0.5, 0.5, 0.5)
+        }
+        Shading::Phong => phong(pos, normals(pos, camera, params), camera, params),
+        Shading::Depth => {
+            let depth = (pos - cam_pos(camera)).magnitude();
+            Vector3::from([1.0 / (1.0 + depth); 3])
+        }
+        Shading::Steps | Shading::Iterations => {
+            let iterations = mandelbrot(to_quat(pos, params), params).iterations;
+            Vector3::from([iterations as f32 / params.max_iterations as f32; 3])
+        }
+    }
+}
+
+/// `cam.pos` in the shader, the camera position rounded to `f32`.
+fn cam_pos(camera: &Camera) -> Vector4<f32> {
+    camera.position().to_f32().into()
+}
+
+/// Maps a point into quaternion space, by moving it along w to the slice
+/// and rotating that.
+pub fn to_quat(pos: Vector4<f32>, params: &Params) -> Quaternion {
+    let slice_w = Vector4::new(0.0, 0.0, 0.0, params.slice_w);
+    (Matrix4::from(params.slice_rotation) * (pos + slice_w)).into()
+}
+
+/// Marches a ray from the camera through `real_pos` and returns the linear
+/// RGB color it ends up with.
+pub fn get_color(real_pos: Vector4<f32>, camera: &Camera, params: &Params) -> Vector3<f32> {
+    let ray_direction = real_pos.normalize();
+    let mut ray_pos = real_pos + cam_pos(camera);
+
+    let mut distance = de(ray_pos, camera, params);
+    let mut steps = 0;
+
+    while steps <= params.max_steps
+        && distance > params.min_distance
+        && distance < params.max_distance
+    {
+        ray_pos += ray_direction * distance;
+        distance = de(ray_pos, camera, params);
+        steps += 1;
+    }
+
+    if params.shading == Shading::Steps {
+        Vector3::new(steps as f32 / params.max_steps as f32, 0.0, 0.0)
+    } else if distance <= params.min_distance {
+        on_hit(ray_pos, camera, params)
+    } else {
+        Vector3::new(0.0, 0.0, 0.0)
+    }
+}
+
+/// A vector of four double-floats, `Df4` in the shader.
+type Df4 = [Df64; 4];
+
+fn df(hi: Vector4<f32>) -> Df4 {
+    let hi: [f32; 4] = hi.into();
+    hi.map(Df64::from)
+}
+
+fn df_splat(a: Df4, i: usize) -> Df4 {
+    [a[i]; 4]
+}
+
+fn df_add(a: Df4, b: Df4) -> Df4 {
+    [0, 1, 2, 3].map(|i| a[i] + b[i])
+}
+
+fn df_neg(a: Df4) -> Df4 {
+    a.map(|x| -x)
+}
+
+fn df_mul(a: Df4, b: Df4) -> Df4 {
+    [0, 1, 2, 3].map(|i| a[i] * b[i])
+}
+
+fn df_mat_mul(m: Matrix4<f32>, v: Df4) -> Df4 {
+    let mut result = df_mul(df(m.x), df_splat(v, 0));
+    result = df_add(result, df_mul(df(m.y), df_splat(v, 1)));
+    result = df_add(result, df_mul(df(m.z), df_splat(v, 2)));
+    df_add(result, df_mul(df(m.w), df_splat(v, 3)))
+}
+
+fn hi(a: Df4) -> Vector4<f32> {
+    a.map(|x| x.hi).into()
+}
+
+fn lo(a: Df4) -> Vector4<f32> {
+    a.map(|x| x.lo).into()
+}
+
+/// Multiplies each part by a unit quaternion, which is exact.
+fn df_unit_mul(unit: Quaternion, q: Df4) -> Df4 {
+    let hi = <[f32; 4]>::from(unit * Quaternion::from(hi(q)));
+    let lo = <[f32; 4]>::from(unit * Quaternion::from(lo(q)));
+    [0, 1, 2, 3].map(|i| Df64::new(hi[i], lo[i]))
+}
+
+fn quaternion_mul_df64(a: Df4, b: Df4) -> Df4 {
+    // ab = a.r b + a.i 𝐢b + a.j 𝐣b + a.k 𝐤b
+    let mut result = df_mul(df_splat(a, 0), b);
+    result = df_add(
+        result,
+        df_mul(df_splat(a, 1), df_unit_mul(Quaternion::I, b)),
+    );
+    result = df_add(
+        result,
+        df_mul(df_splat(a, 2), df_unit_mul(Quaternion::J, b)),
+    );
+    df_add(
+        result,
+        df_mul(df_splat(a, 3), df_unit_mul(Quaternion::K, b)),
+    )
+}
+
+/// `mandelbrot` with `z` and `c` in double precision. The derivatives don't
+/// need it.
+fn mandelbrot_df64(pos: Df4, params: &Params) -> Escape {
+    let mut iters = 0;
+    let (c, dc) = if params.julia {
+        (df(params.julia_c.into()), 0.0)
+    } else {
+        (pos, 1.0)
+    };
+    let mut z = pos;
+    let mut derivatives = start_derivatives();
+    let flips = &params.flips[..params.flips.len().min(MAX_FLIPS)];
+    let mut flips_done = 0;
+
+    while iters <= params.max_iterations {
+        if !flips.is_empty() && params.flip_schedule.applies(iters) {
+            let flip = Matrix4::from(flips[flips_done % flips.len()].matrix);
+            z = df_mat_mul(flip, z);
+            derivatives = flip_derivatives(derivatives, flip);
+            flips_done += 1;
+        }
+
+        derivatives = step_derivatives(derivatives, hi(z).into(), dc, params);
+
+        if params.power == 2.0 {
+            z = df_add(quaternion_mul_df64(z, z), c);
+        } else {
+            // There's no double precision polar form
+            let power = Quaternion::from(hi(z)).powf(params.power);
+            z = df_add(df(power.into()), c);
+        }
+
+        let z2 = hi(z).magnitude2();
+        if z2 > params.bailout {
+            break;
+        }
+
+        iters += 1;
+    }
+
+    escape(hi(z).into(), derivatives, iters, params)
+}
+
+fn to_quat_df64(pos: Df4, params: &Params) -> Df4 {
+    let pos = df_add(pos, df(Vector4::new(0.0, 0.0, 0.0, params.slice_w)));
+    df_mat_mul(Matrix4::from(params.slice_rotation), pos)
+}
+
+fn moved_df64(pos: Df4, offset: Vector4<f32>) -> Df4 {
+    df_add(pos, df(offset))
+}
+
+fn de_df64(pos: Df4, camera: &Camera, params: &Params) -> f32 {
+    let escape = mandelbrot_df64(to_quat_df64(pos, params), params);
+    let distance = if params.estimator == Estimator::Gradient {
+        let samples = gradient_samples(camera, params, |step| {
+            mandelbrot_df64(to_quat_df64(moved_df64(pos, step), params), params)
+        });
+        gradient_estimate(escape, samples, params)
+    } else {
+        escape.distance
+    };
+
+    params.fudge * distance
+}
+
+fn normals_df64(pos: Df4, camera: &Camera, params: &Params) -> Vector4<f32> {
+    let gradient = differences(camera, params, |step| {
+        de_df64(moved_df64(pos, step), camera, params)
+    });
+    to_world(gradient, camera).normalize()
+}
+
+fn cam_pos_df64(camera: &Camera) -> Df4 {
+    camera.position().to_f64().map(Df64::from)
+}
+
+fn on_hit_df64(pos: Df4, camera: &Camera, params: &Params) -> Vector3<f32> {
+    match params.shading {
+        Shading::Normals => {
+            normals_df64(pos, camera, params).truncate() * 0.5 + Vector3::new(0.5, 0.5, 0.5)
+        }
+        Shading::Phong => phong(hi(pos), normals_df64(pos, camera, params), camera, params),
+        Shading::Depth => {
+            let offset = df_add(pos, df_neg(cam_pos_df64(camera)));
+            Vector3::from([1.0 / (1.0 + hi(offset).magnitude()); 3])
+        }
+        Shading::Steps | Shading::Iterations => {
+            let iterations = mandelbrot_df64(to_quat_df64(pos, params), params).iterations;
+            Vector3::from([iterations as f32 / params.max_iterations as f32; 3])
+        }
+    }
+}
+
+/// `get_color` in double precision, from the camera rotation and the
+/// unrotated `local_pos` instead of `real_pos`.
+pub fn get_color_df64(local_pos: Vector3<f32>, camera: &Camera, params: &Params) -> Vector3<f32> {
+    let ray_direction = local_pos.normalize();
+    // Unlike `get_color` this starts at the camera itself, as the near plane
+    // is further away than the details double precision is for
+    let mut ray_pos = cam_pos_df64(camera);
+    let rot = Matrix4::from(camera.rot());
+
+    let mut distance = de_df64(ray_pos, camera, params);
+    let mut steps = 0;
+
+    while steps <= params.max_steps
+        && distance > params.min_distance
+        && distance < params.max_distance
+    {
+        // Rotating the step in double precision too, so the tiny sideways
+        // parts of it don't get rounded away
+        let step = df(ray_direction.extend(0.0) * distance);
+        ray_pos = df_add(ray_pos, df_mat_mul(rot, step));
+        distance = de_df64(ray_pos, camera, params);
+        steps += 1;
+    }
+
+    if params.shading == Shading::Steps {
+        Vector3::new(steps as f32 / params.max_steps as f32, 0.0, 0.0)
+    } else if distance <= params.min_distance {
+        on_hit_df64(ray_pos, camera, params)
+    } else {
+        Vector3::new(0.0, 0.0, 0.0)
+    }
+}
+
+/// `mandelbrot` for the point `offset` away from the reference point in
+/// quaternion space, iterating only its difference δ from `orbit`. See
+/// `perturbation` for how that works.
+pub fn mandelbrot_perturbed(offset: Quaternion, orbit: &ReferenceOrbit, params: &Params) -> Escape {
+    let mut iters = 0;
+    // δc is how far c is from the reference's c, dc its derivative with
+    // respect to pos
+    let (delta_c, dc) = if params.julia {
+        (Quaternion::ZERO, 0.0)
+    } else {
+        (offset, 1.0)
+    };
+    let mut delta = offset;
+    // The orbit being followed and where in it we are
+    let mut reference = &orbit.main;
+    let mut m = 0;
+    let mut derivatives = start_derivatives();
+    let flips = &params.flips[..params.flips.len().min(MAX_FLIPS)];
+    let mut flips_done = 0;
+
+    // Powers other than 2 can't be perturbed, so those iterate z itself
+    // like `mandelbrot` does, which is also what happens when the reference
+    // runs out and we can't rebase
+    let mut perturbed = params.power == 2.0;
+    let c = if params.julia {
+        params.julia_c.into()
+    } else {
+        Quaternion::from(orbit.main[0]) + offset
+    };
+    let mut z = Quaternion::from(orbit.main[0]) + offset;
+
+    while iters <= params.max_iterations {
+        let mut z_ref = Quaternion::from(reference[m]);
+        if !flips.is_empty() && params.flip_schedule.applies(iters) {
+            let flip = Matrix4::from(flips[flips_done % flips.len()].matrix);
+            z = (flip * Vector4::from(z)).into();
+            z_ref = (flip * Vector4::from(z_ref)).into();
+            delta = (flip * Vector4::from(delta)).into();
+            derivatives = flip_derivatives(derivatives, flip);
+            flips_done += 1;
+        }
+
+        derivatives = step_derivatives(derivatives, z, dc, params);
+
+        if perturbed {
+            // (Z + δ)² + C + δc = Z² + C + (Zδ + δZ + δ² + δc)
+            delta = z_ref * delta + delta * z_ref + delta * delta + delta_c;
+            m += 1;
+            z = Quaternion::from(reference[m]) + delta;
+        } else if params.power == 2.0 {
+            z = z * z + c;
+        } else {
+            z = z.powf(params.power) + c;
+        }
+
+        let z2 = z.norm_squared();
+        if z2 > params.bailout {
+            break;
+        }
+
+        iters += 1;
+
+        let at_end = m + 1 >= reference.len();
+        if perturbed && (z2 < delta.norm_squared() || at_end) {
+            if iters.is_multiple_of(orbit.rebase_period) {
+                // Carries on from the rebase orbit's 0, so δ is all of z
+                delta = z;
+                reference = &orbit.rebase;
+                m = 0;
+            } else if at_end {
+                perturbed = false;
+            }
+        }
+    }
+
+    escape(z, derivatives, iters, params)
+}
+
+/// Offsets from the camera don't move along w to the slice, they only get
+/// rotated.
+fn offset_to_quat(offset: Vector4<f32>, params: &Params) -> Quaternion {
+    (Matrix4::from(params.slice_rotation) * offset).into()
+}
+
+fn de_perturbed(
+    offset: Vector4<f32>,
+    camera: &Camera,
+    orbit: &ReferenceOrbit,
+    params: &Params,
+) -> f32 {
+    let escape = mandelbrot_perturbed(offset_to_quat(offset, params), orbit, params);
+    let distance = if params.estimator == Estimator::Gradient {
+        let samples = gradient_samples(camera, params, |step| {
+            mandelbrot_perturbed(offset_to_quat(offset + step, params), orbit, params)
+        });
+        gradient_estimate(escape, samples, params)
+    } else {
+        escape.distance
+    };
+
+    params.fudge * distance
+}
+
+fn normals_perturbed(
+    offset: Vector4<f32>,
+    camera: &Camera,
+    orbit: &ReferenceOrbit,
+    params: &Params,
+) -> Vector4<f32> {
+    let gradient = differences(camera, params, |step| {
+        de_perturbed(offset + step, camera, orbit, params)
+    });
+    to_world(gradient, camera).normalize()
+}
+
+fn on_hit_perturbed(
+    offset: Vector4<f32>,
+    camera: &Camera,
+    orbit: &ReferenceOrbit,
+    params: &Params,
+) -> Vector3<f32> {
+    match params.shading {
+        Shading::Normals => {
+            normals_perturbed(offset, camera, orbit, params).truncate() * 0.5
+                + Vector3::new(0.5, 0.5, 0.5)
+        }
+        Shading::Phong => phong(
+            cam_pos(camera) + offset,
+            normals_perturbed(offset, camera, orbit, params),
+            camera,
+            params,
+        ),
+        Shading::Depth => Vector3::from([1.0 / (1.0 + offset.magnitude()); 3]),
+        Shading::Steps | Shading::Iterations => {
+            let iterations =
+                mandelbrot_perturbed(offset_to_quat(offset, params), orbit, params).iterations;
+            Vector3::from([iterations as f32 / params.max_iterations as f32; 3])
+        }
+    }
+}
+
+/// `get_color` with the ray marched as an offset from the camera, which
+/// `orbit` has to be the `ReferenceOrbit` of.
+pub fn get_color_perturbed(
+    real_pos: Vector4<f32>,
+    camera: &Camera,
+    orbit: &ReferenceOrbit,
+    params: &Params,
+) -> Vector3<f32> {
+    let ray_direction = real_pos.normalize();
+    // Starts at the camera, like `get_color_df64`
+    let mut offset = Vector4::zero();
+
+    let mut distance = de_perturbed(offset, camera, orbit, params);
+    let mut steps = 0;
+
+    while steps <= params.max_steps
+        && distance > params.min_distance
+        && distance < params.max_distance
+    {
+        offset += ray_direction * distance;
+        distance = de_perturbed(offset, camera, orbit, params);
+        steps += 1;
+    }
+
+    if params.shading == Shading::Steps {
+        Vector3::new(steps as f32 / params.max_steps as f32, 0.0, 0.0)
+    } else if distance <= params.min_distance {
+        on_hit_perturbed(offset, camera, orbit, params)
+    } else {
+        Vector3::new(0.0, 0.0, 0.0)
+    }
+}
+
+/// The distance estimate at the camera, in the precision `params.precision`
+/// asks for, which is what the camera's speed goes by.
+pub fn distance_at(camera: &Camera, params: &Params) -> f32 {
+    distance_with_orbit(camera, None, params)
+}
+
+/// `distance_at`, with the camera's `ReferenceOrbit` for perturbation
+/// passed in if it's already been iterated.
+pub fn distance_with_orbit(
+    camera: &Camera,
+    orbit: Option<&ReferenceOrbit>,
+    params: &Params,
+) -> f32 {
+    match params.precision {
+        Precision::Single => de(cam_pos(camera), camera, params),
+        Precision::Double => de_df64(cam_pos_df64(camera), camera, params),
+        Precision::Perturbation => match orbit {
+            Some(orbit) => de_perturbed(Vector4::zero(), camera, orbit, params),
+            None => {
+                let orbit = ReferenceOrbit::new(camera.position(), params);
+                de_perturbed(Vector4::zero(), camera, &orbit, params)
+            }
+        },
+    }
+}
+
+/// The point of the surface that the ray through `screen_pos` hits, marched
+/// like `get_color` does but in the precision `params.precision` asks for.
+pub fn surface_point(camera: &Camera, screen_pos: [f32; 2], params: &Params) -> Option<Position> {
+    let real_pos = camera.real_pos(screen_pos);
+    let ray_direction = real_pos.normalize();
+    let moved = |offset: Vector4<f32>| {
+        let mut pos = camera.position().clone();
+        pos.translate(offset.map(f64::from).into());
+        pos
+    };
+    // Perturbation measures everything from the camera's reference orbit,
+    // which only has to be iterated once
+    let orbit = (params.precision == Precision::Perturbation)
+        .then(|| ReferenceOrbit::new(camera.position(), params));
+    let de_at = |offset| match &orbit {
+        Some(orbit) => de_perturbed(offset, camera, orbit, params),
+        None => {
+            let mut camera = camera.clone();
+            camera.set_position(moved(offset));
+            distance_at(&camera, params)
+        }
+    };
+
+    let mut offset = real_pos;
+    let mut distance = de_at(offset);
+    let mut steps = 0;
+
+    while steps <= params.max_steps
+        && distance > params.min_distance
+        && distance < params.max_distance
+    {
+        offset += ray_direction * distance;
+        distance = de_at(offset);
+        steps += 1;
+    }
+
+    (distance <= params.min_distance).then(|| moved(offset))
+}
+
+/// Where on the screen the center of pixel `(x, y)` is.
+fn pixel_center(x: u32, y: u32, width: u32, height: u32) -> [f32; 2] {
+    screen_point(
+        (x as f32 + 0.5) / width as f32,
+        (y as f32 + 0.5) / height as f32,
+    )
+}
+
+/// The conversion done when the shader writes to an sRGB texture.
+fn linear_to_srgb(c: f32) -> u8 {
+    let c = c.clamp(0.0, 1.0);
+    let srgb = if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    (srgb * 255.0).round() as u8
+}
+
+/// Renders one `width` by `height` frame as seen from `camera`, the same
+/// way `HeadlessRenderer::render` does, spreading the rows over all cores.
+pub fn render(camera: &Camera, params: &Params, width: u32, height: u32) -> Image {
+    // There are no rows to split the work into, and no aspect either
+    if width == 0 || height == 0 {
+        return Image::new(width, height);
+    }
+
+    let mut camera = camera.clone();
+    camera.set_aspect(width, height);
+    let camera = &camera;
+    // What `write_reference_orbit` uploads for the shader
+    let orbit = (params.precision == Precision::Perturbation)
+        .then(|| ReferenceOrbit::new(camera.position(), params));
+
+    let mut image = Image::new(width, height);
+
+    image
+        .pixels
+        .par_chunks_mut(width as usize * 4)
+        .enumerate()
+        .for_each(|(y, row)| {
+            for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+                // What `vs_main` hands to `fs_main` for this pixel
+                let screen_pos = pixel_center(x as u32, y as u32, width, height);
+                let color = match params.precision {
+                    Precision::Single => get_color(camera.real_pos(screen_pos), camera, params),
+                    Precision::Double => {
+                        get_color_df64(camera.local_pos(screen_pos), camera, params)
+                    }
+                    Precision::Perturbation => get_color_perturbed(
+                        camera.real_pos(screen_pos),
+                        camera,
+                        orbit.as_ref().unwrap(),
+                        params,
+                    ),
+                };
+
+                pixel.copy_from_slice(&[
+                    linear_to_srgb(color.x),
+                    linear_to_srgb(color.y),
+                    linear_to_srgb(color.z),
+                    255,
+                ]);
+            }
+        });
+
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gradient_estimate_is_finite_after_many_iterations() {
+        let params = Params {
+            estimator: Estimator::Gradient,
+            ..Params::default()
+        };
+        let camera = Camera::new([0.0, 0.0, -3.0], 0.0, 0.0);
+        let escape = |pos: Vector4<f32>| mandelbrot(to_quat(pos, &params), &params);
+
+        // Bisect towards the surface until it takes a lot of iterations to
+        // escape, which overflowed n^iterations at power 2
+        let (mut inside, mut outside) = (Vector4::zero(), Vector4::new(-2.0, 1.4, -2.0, 0.0));
+        for _ in 0..40 {
+            let middle = (inside + outside) / 2.0;
+            if escape(middle).iterations <= params.max_iterations {
+                outside = middle;
+            } else {
+                inside = middle;
+            }
+        }
+
+        assert!(escape(outside).iterations >= 128);
+        assert!(de(outside, &camera, &params).is_finite());
+    }
+
+    #[test]
+    fn empty_images_render_empty() {
+        let camera = Camera::default();
+        for (width, height) in [(0, 4), (4, 0), (0, 0)] {
+            let image = render(&camera, &Params::default(), width, height);
+            assert_eq!((image.width, image.height), (width, height));
+            assert!(image.pixels.is_empty());
+        }
+    }
+}
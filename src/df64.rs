@@ -0,0 +1,159 @@
+//! Double-float arithmetic: a number stored as the unevaluated sum of two
+//! `f32`s, which gets about 48 bits of mantissa out of hardware that only
+//! does single precision.
+//!
+//! The shader has the same functions working on `vec4`s, see the `Df4`
+//! section of `shader.wgsl`. Both rely on every operation being rounded
+//! exactly as written, so don't "simplify" any of them.
+
+use std::ops::{Add, Mul, Neg, Sub};
+
+/// `hi + lo`, where `lo` is at most half an ulp of `hi`.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Df64 {
+    pub hi: f32,
+    pub lo: f32,
+}
+
+impl Df64 {
+    pub const ZERO: Self = Self::new(0.0, 0.0);
+
+    pub const fn new(hi: f32, lo: f32) -> Self {
+        Self { hi, lo }
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.hi as f64 + self.lo as f64
+    }
+}
+
+/// `a + b` and its rounding error.
+fn two_sum(a: f32, b: f32) -> Df64 {
+    let s = a + b;
+    let v = s - a;
+    let e = (a - (s - v)) + (b - v);
+    Df64::new(s, e)
+}
+
+/// `two_sum` for when `|a| >= |b|`.
+fn quick_two_sum(a: f32, b: f32) -> Df64 {
+    let s = a + b;
+    let e = b - (s - a);
+    Df64::new(s, e)
+}
+
+/// Splits `a` into two halves of 12 bits that can be multiplied exactly.
+fn split(a: f32) -> Df64 {
+    let t = 4097.0 * a;
+    let hi = t - (t - a);
+    Df64::new(hi, a - hi)
+}
+
+/// `a * b` and its rounding error.
+fn two_prod(a: f32, b: f32) -> Df64 {
+    let p = a * b;
+    let a = split(a);
+    let b = split(b);
+    let e = ((a.hi * b.hi - p) + a.hi * b.lo + a.lo * b.hi) + a.lo * b.lo;
+    Df64::new(p, e)
+}
+
+impl From<f32> for Df64 {
+    fn from(hi: f32) -> Self {
+        Self::new(hi, 0.0)
+    }
+}
+
+impl From<f64> for Df64 {
+    fn from(x: f64) -> Self {
+        let hi = x as f32;
+        Self::new(hi, (x - hi as f64) as f32)
+    }
+}
+
+impl Add for Df64 {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        let s = two_sum(self.hi, other.hi);
+        let t = two_sum(self.lo, other.lo);
+        let r = quick_two_sum(s.hi, s.lo + t.hi);
+        quick_two_sum(r.hi, r.lo + t.lo)
+    }
+}
+
+impl Neg for Df64 {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::new(-self.hi, -self.lo)
+    }
+}
+
+impl Sub for Df64 {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self + -other
+    }
+}
+
+impl Mul for Df64 {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        let p = two_prod(self.hi, other.hi);
+        quick_two_sum(p.hi, p.lo + (self.hi * other.lo + self.lo * other.hi))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    // A little worse than the 2^-48 the format can hold, as the error
+    // terms themselves get rounded
+    const EPSILON: f64 = 1e-13;
+
+    fn df64() -> impl Strategy<Value = f64> {
+        (-1e3f64..1e3).prop_filter("too close to zero", |x| x.abs() > 1e-3)
+    }
+
+    fn close(actual: Df64, expected: f64, scale: f64) -> bool {
+        (actual.to_f64() - expected).abs() <= EPSILON * scale
+    }
+
+    proptest! {
+        #[test]
+        fn round_trips_through_f64(x in df64()) {
+            prop_assert!(close(Df64::from(x), x, x.abs()));
+        }
+
+        #[test]
+        fn adds_like_f64(a in df64(), b in df64()) {
+            let sum = Df64::from(a) + Df64::from(b);
+            prop_assert!(close(sum, a + b, a.abs().max(b.abs())));
+        }
+
+        #[test]
+        fn subtracts_like_f64(a in df64(), b in df64()) {
+            let difference = Df64::from(a) - Df64::from(b);
+            prop_assert!(close(difference, a - b, a.abs().max(b.abs())));
+        }
+
+        #[test]
+        fn multiplies_like_f64(a in df64(), b in df64()) {
+            let product = Df64::from(a) * Df64::from(b);
+            prop_assert!(close(product, a * b, (a * b).abs()));
+        }
+    }
+
+    #[test]
+    fn keeps_what_f32_loses() {
+        let tiny = 1e-12;
+        let sum = Df64::from(1.0f32) + Df64::from(tiny);
+        assert_eq!(1.0f32 + tiny as f32, 1.0);
+        assert!(close(sum, 1.0 + tiny, 1.0));
+    }
+}
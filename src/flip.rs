@@ -0,0 +1,269 @@
+//! The linear maps applied to `z` between iterations.
+//!
+//! The plain quaternion mandelbrot set is rotationally symmetric around the
+//! real axis. Flipping `z` with a map that doesn't commute with that
+//! rotation, like the README's `z.yz = -z.zy`, breaks the symmetry and
+//! gives the set its more interesting shape.
+
+use std::{fmt, str::FromStr};
+
+use cgmath::{Matrix4, SquareMatrix};
+
+/// How many flips the shader can alternate between.
+pub const MAX_FLIPS: usize = 4;
+
+const AXES: [char; 4] = ['x', 'y', 'z', 'w'];
+
+/// A 4x4 matrix applied to `z` as a vector with the real part in `x`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Flip {
+    /// Column major
+    pub matrix: [[f32; 4]; 4],
+}
+
+impl Flip {
+    /// Leaves `z` as it is.
+    pub fn identity() -> Self {
+        Self::from_matrix(Matrix4::identity().into())
+    }
+
+    pub fn from_matrix(matrix: [[f32; 4]; 4]) -> Self {
+        Self { matrix }
+    }
+
+    /// Builds a flip that moves axis `axes[i].0` to axis `i` and multiplies
+    /// it by the sign `axes[i].1`. Axes are numbered x = 0 ... w = 3.
+    ///
+    /// Returns `None` if the axes aren't a permutation.
+    pub fn signed_permutation(axes: [(usize, f32); 4]) -> Option<Self> {
+        let mut seen = [false; 4];
+        let mut matrix = [[0.0; 4]; 4];
+
+        for (row, (axis, sign)) in axes.into_iter().enumerate() {
+            if axis >= 4 || seen[axis] {
+                return None;
+            }
+            seen[axis] = true;
+            // Column major, so matrix[column][row]
+            matrix[axis][row] = sign.signum();
+        }
+
+        Some(Self { matrix })
+    }
+
+    /// The axes and signs this flip was built from, if it is a signed
+    /// permutation.
+    fn as_signed_permutation(&self) -> Option<[(usize, f32); 4]> {
+        let mut axes = [(0, 0.0); 4];
+
+        for (row, axis) in axes.iter_mut().enumerate() {
+            let mut entries = (0..4).filter(|&col| self.matrix[col][row] != 0.0);
+            let col = entries.next()?;
+            let sign = self.matrix[col][row];
+            if entries.next().is_some() || sign.abs() != 1.0 {
+                return None;
+            }
+            *axis = (col, sign);
+        }
+
+        Self::signed_permutation(axes).map(|_| axes)
+    }
+}
+
+impl Default for Flip {
+    /// The README's `z.yz = -z.zy`.
+    fn default() -> Self {
+        "x,-z,-y,w".parse().unwrap()
+    }
+}
+
+/// Written the same way `from_str` reads it, as a signed permutation if
+/// possible and the 16 matrix entries column by column otherwise.
+impl fmt::Display for Flip {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let terms: Vec<String> = match self.as_signed_permutation() {
+            Some(axes) => axes
+                .iter()
+                .map(|&(axis, sign)| {
+                    let sign = if sign < 0.0 { "-" } else { "" };
+                    format!("{sign}{}", AXES[axis])
+                })
+                .collect(),
+            None => self.matrix.iter().flatten().map(f32::to_string).collect(),
+        };
+
+        f.write_str(&terms.join(","))
+    }
+}
+
+/// Parses a signed permutation like `x,-z,-y,w`, which is the new `z` in
+/// terms of the old one, or 16 comma separated matrix entries given column
+/// by column.
+impl FromStr for Flip {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let terms: Vec<&str> = s.split(',').map(str::trim).collect();
+
+        match terms.len() {
+            4 => {
+                let mut axes = [(0, 1.0); 4];
+                for (axis, term) in axes.iter_mut().zip(&terms) {
+                    let (sign, name) = match term.strip_prefix('-') {
+                        Some(name) => (-1.0, name),
+                        None => (1.0, term.strip_prefix('+').unwrap_or(term)),
+                    };
+                    let index = AXES
+                        .iter()
+                        .position(|a| name.len() == 1 && name.starts_with(*a))
+                        .ok_or_else(|| format!("unknown axis {term:?}, expected x, y, z or w"))?;
+                    *axis = (index, sign);
+                }
+
+                Self::signed_permutation(axes)
+                    .ok_or_else(|| format!("{s:?} uses an axis more than once"))
+            }
+            16 => {
+                let mut matrix = [[0.0; 4]; 4];
+                for (entry, term) in matrix.iter_mut().flatten().zip(&terms) {
+                    *entry = term.parse().map_err(|e| format!("{term:?}: {e}"))?;
+                }
+                Ok(Self::from_matrix(matrix))
+            }
+            n => Err(format!(
+                "expected 4 axes like x,-z,-y,w or 16 matrix entries but got {n} values"
+            )),
+        }
+    }
+}
+
+/// When the flips are applied to `z`.
+///
+/// Every time a flip is applied the next one in `Params::flips` is used,
+/// so several flips alternate.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum FlipSchedule {
+    /// Only to the starting `z`, which is what the README describes
+    #[default]
+    Once,
+    /// Before every iteration
+    EveryIteration,
+    /// Before every nth iteration, starting with the first
+    EveryNth(u32),
+}
+
+impl FlipSchedule {
+    /// Every how many iterations a flip happens, 0 meaning only once. This
+    /// is how the shader gets it.
+    pub(crate) fn period(self) -> u32 {
+        match self {
+            FlipSchedule::Once => 0,
+            FlipSchedule::EveryIteration => 1,
+            FlipSchedule::EveryNth(n) => n.max(1),
+        }
+    }
+
+    /// Whether a flip happens before iteration `iteration`.
+    pub(crate) fn applies(self, iteration: u32) -> bool {
+        match self.period() {
+            0 => iteration == 0,
+            period => iteration.is_multiple_of(period),
+        }
+    }
+
+    /// The schedule after this one when cycling through them with a key,
+    /// going up to every 4th iteration.
+    pub fn next(self) -> Self {
+        match self {
+            FlipSchedule::Once => FlipSchedule::EveryIteration,
+            FlipSchedule::EveryIteration => FlipSchedule::EveryNth(2),
+            FlipSchedule::EveryNth(n) if n < 4 => FlipSchedule::EveryNth(n + 1),
+            FlipSchedule::EveryNth(_) => FlipSchedule::Once,
+        }
+    }
+}
+
+impl fmt::Display for FlipSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FlipSchedule::Once => f.write_str("once"),
+            FlipSchedule::EveryIteration => f.write_str("every"),
+            FlipSchedule::EveryNth(n) => write!(f, "every-{n}"),
+        }
+    }
+}
+
+/// Parses `once`, `every` or `every-N`.
+impl FromStr for FlipSchedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "once" => Ok(FlipSchedule::Once),
+            "every" => Ok(FlipSchedule::EveryIteration),
+            _ => s
+                .strip_prefix("every-")
+                .and_then(|n| n.parse().ok())
+                .filter(|&n| n > 0)
+                .map(FlipSchedule::EveryNth)
+                .ok_or_else(|| {
+                    format!("unknown flip schedule {s:?}, expected once, every or every-N")
+                }),
+        }
+    }
+}
+
+/// Flips that the F key cycles through.
+pub const PRESETS: [&str; 6] = [
+    "x,-z,-y,w",
+    "x,y,z,w",
+    "x,z,y,w",
+    "x,-w,z,-y",
+    "x,y,-w,z",
+    "w,y,z,x",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_is_the_readme_flip() {
+        let flip = Flip::default();
+        let z = cgmath::Vector4::new(1.0, 2.0, 3.0, 4.0);
+
+        assert_eq!(
+            Matrix4::from(flip.matrix) * z,
+            cgmath::Vector4::new(1.0, -3.0, -2.0, 4.0)
+        );
+    }
+
+    #[test]
+    fn flips_round_trip_through_strings() {
+        for preset in PRESETS {
+            assert_eq!(preset.parse::<Flip>().unwrap().to_string(), preset);
+        }
+
+        let matrix = "1,0,0,0,0,0.5,0,0,0,0,1,0,0,0,0,1";
+        assert_eq!(matrix.parse::<Flip>().unwrap().to_string(), matrix);
+    }
+
+    #[test]
+    fn repeated_axes_are_rejected() {
+        assert!("x,y,y,w".parse::<Flip>().is_err());
+        assert!("x,y,q,w".parse::<Flip>().is_err());
+    }
+
+    #[test]
+    fn schedules() {
+        assert!(FlipSchedule::Once.applies(0));
+        assert!(!FlipSchedule::Once.applies(1));
+        assert!((0..5).all(|i| FlipSchedule::EveryIteration.applies(i)));
+        assert_eq!(
+            (0..7)
+                .map(|i| FlipSchedule::EveryNth(3).applies(i))
+                .collect::<Vec<_>>(),
+            [true, false, false, true, false, false, true]
+        );
+    }
+}
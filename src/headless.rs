@@ -0,0 +1,250 @@
+use std::{fmt, num::NonZeroU32, sync::mpsc};
+
+use crate::{image::Image, *};
+
+// Same as the surface in `State`, so the shader output looks identical
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+#[derive(Debug)]
+pub enum HeadlessError {
+    NoAdapter,
+    RequestDevice(wgpu::RequestDeviceError),
+    BufferMap(wgpu::BufferAsyncError),
+}
+
+impl fmt::Display for HeadlessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HeadlessError::NoAdapter => write!(f, "no suitable graphics adapter found"),
+            HeadlessError::RequestDevice(e) => write!(f, "failed to request device: {e}"),
+            HeadlessError::BufferMap(e) => write!(f, "failed to read back the image: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for HeadlessError {}
+
+/// Renders the fractal into an offscreen texture instead of a window,
+/// so images can be made on machines without a display.
+///
+/// Images bigger than the device's maximum texture size are rendered in
+/// tiles and stitched together.
+pub struct HeadlessRenderer {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    render_pipeline: wgpu::RenderPipeline,
+    index_buffer: wgpu::Buffer,
+    num_indices: u32,
+    bind_group: wgpu::BindGroup,
+    zoom_uniform: Zoom,
+    zoom_buffer: wgpu::Buffer,
+    camera_buffer: wgpu::Buffer,
+    params_buffer: wgpu::Buffer,
+    orbit_buffer: wgpu::Buffer,
+    tile_size: u32,
+}
+
+impl HeadlessRenderer {
+    pub async fn new() -> Result<Self, HeadlessError> {
+        Self::with_adapter(false).await
+    }
+
+    /// Like `new`, but only uses a software adapter so the output doesn't
+    /// depend on the GPU. Useful on CI machines that don't have one.
+    pub async fn new_fallback() -> Result<Self, HeadlessError> {
+        Self::with_adapter(true).await
+    }
+
+    async fn with_adapter(force_fallback_adapter: bool) -> Result<Self, HeadlessError> {
+        let instance = create_instance();
+
+        let adapter = create_adapter(&instance, None, force_fallback_adapter)
+            .await
+            .ok_or(HeadlessError::NoAdapter)?;
+        let (device, queue) = create_device_queue(&adapter)
+            .await
+            .map_err(HeadlessError::RequestDevice)?;
+
+        let (
+            (zoom_uniform, zoom_buffer),
+            (_, camera_buffer),
+            (_, params_buffer),
+            orbit_buffer,
+            bind_group,
+            bind_group_layout,
+        ) = generate_bindgroups(&device);
+
+        let shader = create_shader(&device);
+
+        let (_, index_buffer) = create_buffers(&device);
+
+        let render_pipeline = create_render_pipeline(&device, &bind_group_layout, &shader, FORMAT);
+
+        let tile_size = device.limits().max_texture_dimension_2d;
+
+        Ok(Self {
+            device,
+            queue,
+            render_pipeline,
+            index_buffer,
+            num_indices: INDICES.len() as u32,
+            bind_group,
+            zoom_uniform,
+            zoom_buffer,
+            camera_buffer,
+            params_buffer,
+            orbit_buffer,
+            tile_size,
+        })
+    }
+
+    /// Renders one `width` by `height` frame as seen from `camera`.
+    pub fn render(
+        &self,
+        camera: &Camera,
+        params: &Params,
+        width: u32,
+        height: u32,
+    ) -> Result<Image, HeadlessError> {
+        let mut camera = camera.clone();
+        camera.set_aspect(width, height);
+
+        self.queue.write_buffer(
+            &self.zoom_buffer,
+            0,
+            bytemuck::cast_slice(&[self.zoom_uniform]),
+        );
+        self.queue.write_buffer(
+            &self.camera_buffer,
+            0,
+            bytemuck::cast_slice(&[CameraUniform::from(&camera)]),
+        );
+        self.queue.write_buffer(
+            &self.params_buffer,
+            0,
+            bytemuck::cast_slice(&[ParamsUniform::from(params)]),
+        );
+        write_reference_orbit(&self.queue, &self.orbit_buffer, &camera, params);
+
+        let mut image = Image::new(width, height);
+
+        for y in (0..height).step_by(self.tile_size as usize) {
+            for x in (0..width).step_by(self.tile_size as usize) {
+                let tile_width = self.tile_size.min(width - x);
+                let tile_height = self.tile_size.min(height - y);
+
+                let vertices = tile_vertices(
+                    x as f32 / width as f32,
+                    y as f32 / height as f32,
+                    (x + tile_width) as f32 / width as f32,
+                    (y + tile_height) as f32 / height as f32,
+                );
+                let tile = self.render_tile(&vertices, tile_width, tile_height)?;
+
+                let row_len = tile_width as usize * 4;
+                for row in 0..tile_height as usize {
+                    let start = ((y as usize + row) * width as usize + x as usize) * 4;
+                    image.pixels[start..start + row_len]
+                        .copy_from_slice(&tile[row * row_len..(row + 1) * row_len]);
+                }
+            }
+        }
+
+        Ok(image)
+    }
+
+    fn render_tile(
+        &self,
+        vertices: &[Vertex],
+        width: u32,
+        height: u32,
+    ) -> Result<Vec<u8>, HeadlessError> {
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Headless Texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        // Rows copied out of a texture have to be padded to a multiple of 256 bytes
+        let row_len = width * 4;
+        let padded_row_len = row_len.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+        let output_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Headless Output Buffer"),
+            size: (padded_row_len * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let vertex_buffer = create_vertex_buffer(&self.device, vertices);
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Headless Encoder"),
+            });
+
+        draw_fractal(
+            &mut encoder,
+            &view,
+            &self.render_pipeline,
+            &self.bind_group,
+            &vertex_buffer,
+            &self.index_buffer,
+            self.num_indices,
+        );
+
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &output_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(padded_row_len),
+                    rows_per_image: NonZeroU32::new(height),
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = output_buffer.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            sender.send(result).unwrap();
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver.recv().unwrap().map_err(HeadlessError::BufferMap)?;
+
+        let pixels = slice
+            .get_mapped_range()
+            .chunks(padded_row_len as usize)
+            .flat_map(|row| &row[..row_len as usize])
+            .copied()
+            .collect();
+        output_buffer.unmap();
+
+        Ok(pixels)
+    }
+}
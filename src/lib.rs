@@ -1,21 +1,65 @@
+use std::time::{Duration, Instant};
+
+use bookmark::{Bookmark, Bookmarks};
 use camera::*;
 use wgpu::util::DeviceExt;
-use winit::{event::WindowEvent, window::Window};
-
+use winit::{
+    dpi::PhysicalPosition,
+    event::{
+        ElementState, KeyboardInput, ModifiersState, MouseButton, VirtualKeyCode, WindowEvent,
+    },
+    window::{CursorGrabMode, Window},
+};
+
+pub use camera::Camera;
+use params::ParamsUniform;
+pub use params::{Derivative, Estimator, Params, Precision, Shading};
+use path::CameraPath;
+use perturbation::{ReferenceOrbit, ORBIT_BUFFER_SIZE};
+pub use position::Position;
+pub use quaternion::Quaternion;
+
+pub mod animation;
+pub mod bookmark;
 mod camera;
+pub mod cpu;
+pub mod df64;
+pub mod flip;
+pub mod headless;
+pub mod image;
+mod params;
+pub mod path;
+pub mod perturbation;
+mod position;
+mod quaternion;
+pub mod rotation;
+
+fn create_instance() -> wgpu::Instance {
+    // The instance is a handle to our GPU
+    // Backends::all => Vulkan + Metal + DX12 + Browser WebGPU
+    wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends: wgpu::Backends::all(),
+        dx12_shader_compiler: Default::default(),
+    })
+}
 
-async fn create_adapter(instance: &wgpu::Instance, surface: &wgpu::Surface) -> wgpu::Adapter {
+async fn create_adapter(
+    instance: &wgpu::Instance,
+    surface: Option<&wgpu::Surface>,
+    force_fallback_adapter: bool,
+) -> Option<wgpu::Adapter> {
     instance
         .request_adapter(&wgpu::RequestAdapterOptions {
             power_preference: wgpu::PowerPreference::HighPerformance,
-            compatible_surface: Some(&surface),
-            force_fallback_adapter: false,
+            compatible_surface: surface,
+            force_fallback_adapter,
         })
         .await
-        .unwrap()
 }
 
-async fn create_device_queue(adapter: &wgpu::Adapter) -> (wgpu::Device, wgpu::Queue) {
+async fn create_device_queue(
+    adapter: &wgpu::Adapter,
+) -> Result<(wgpu::Device, wgpu::Queue), wgpu::RequestDeviceError> {
     adapter
         .request_device(
             &wgpu::DeviceDescriptor {
@@ -32,14 +76,17 @@ async fn create_device_queue(adapter: &wgpu::Adapter) -> (wgpu::Device, wgpu::Qu
             None, // Trace path
         )
         .await
-        .unwrap()
+}
+
+fn create_shader(device: &wgpu::Device) -> wgpu::ShaderModule {
+    device.create_shader_module(wgpu::include_wgsl!("shader.wgsl"))
 }
 
 fn create_render_pipeline(
     device: &wgpu::Device,
     bind_group_layout: &wgpu::BindGroupLayout,
     shader: &wgpu::ShaderModule,
-    config: &wgpu::SurfaceConfiguration,
+    format: wgpu::TextureFormat,
 ) -> wgpu::RenderPipeline {
     let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: Some("Render Pipeline Layout"),
@@ -51,7 +98,7 @@ fn create_render_pipeline(
         label: Some("Render Pipeline"),
         layout: Some(&render_pipeline_layout),
         vertex: wgpu::VertexState {
-            module: &shader,
+            module: shader,
             entry_point: "vs_main",
             buffers: &[Vertex::desc()],
         },
@@ -59,7 +106,7 @@ fn create_render_pipeline(
             module: shader,
             entry_point: "fs_main",
             targets: &[Some(wgpu::ColorTargetState {
-                format: config.format,
+                format,
                 blend: Some(wgpu::BlendState::REPLACE),
                 write_mask: wgpu::ColorWrites::ALL,
             })],
@@ -86,12 +133,16 @@ fn create_render_pipeline(
     })
 }
 
-fn create_buffers(device: &wgpu::Device) -> (wgpu::Buffer, wgpu::Buffer) {
-    let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+fn create_vertex_buffer(device: &wgpu::Device, vertices: &[Vertex]) -> wgpu::Buffer {
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
         label: Some("Vertex Buffer"),
-        contents: bytemuck::cast_slice(VERTICES),
+        contents: bytemuck::cast_slice(vertices),
         usage: wgpu::BufferUsages::VERTEX,
-    });
+    })
+}
+
+fn create_buffers(device: &wgpu::Device) -> (wgpu::Buffer, wgpu::Buffer) {
+    let vertex_buffer = create_vertex_buffer(device, VERTICES);
 
     let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
         label: Some("Index Buffer"),
@@ -118,8 +169,10 @@ async fn setup(
     // State owns the window so this should be safe.
     let surface = unsafe { instance.create_surface(&window) }.unwrap();
 
-    let adapter = create_adapter(&instance, &surface).await;
-    let (device, queue) = create_device_queue(&adapter).await;
+    let adapter = create_adapter(instance, Some(&surface), false)
+        .await
+        .unwrap();
+    let (device, queue) = create_device_queue(&adapter).await.unwrap();
 
     let surface_caps = surface.get_capabilities(&adapter);
     // Shader code in this tutorial assumes an sRGB surface texture. Using a different
@@ -129,8 +182,7 @@ async fn setup(
         .formats
         .iter()
         .copied()
-        .filter(|f| f.describe().srgb)
-        .next()
+        .find(|f| f.describe().srgb)
         .unwrap_or(surface_caps.formats[0]);
 
     let config = wgpu::SurfaceConfiguration {
@@ -159,20 +211,36 @@ fn create_uniform_buffer<T: bytemuck::Pod>(
     })
 }
 
+// A uniform and the buffer it gets uploaded to
+type Uniform<T> = (T, wgpu::Buffer);
+
 fn generate_bindgroups(
     device: &wgpu::Device,
 ) -> (
-    (Zoom, wgpu::Buffer),
-    (Camera, wgpu::Buffer),
+    Uniform<Zoom>,
+    Uniform<CameraUniform>,
+    Uniform<ParamsUniform>,
+    wgpu::Buffer,
     wgpu::BindGroup,
     wgpu::BindGroupLayout,
 ) {
     let zoom_uniform = Zoom::default();
     let zoom_buffer = create_uniform_buffer(device, "Zoom buffer", &[zoom_uniform]);
 
-    let camera_uniform = Camera::default();
+    let camera_uniform = CameraUniform::default();
     let camera_buffer = create_uniform_buffer(device, "Camera buffer", &[camera_uniform]);
 
+    let params_uniform = ParamsUniform::default();
+    let params_buffer = create_uniform_buffer(device, "Params buffer", &[params_uniform]);
+
+    // Only read with `Precision::Perturbation`, see `write_reference_orbit`
+    let orbit_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Orbit buffer"),
+        size: ORBIT_BUFFER_SIZE as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
     let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
         entries: &[
             wgpu::BindGroupLayoutEntry {
@@ -195,8 +263,28 @@ fn generate_bindgroups(
                 },
                 count: None,
             },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
         ],
-        label: Some(format!("bind_group_layout").as_str()),
+        label: Some("bind_group_layout"),
     });
 
     let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
@@ -210,18 +298,87 @@ fn generate_bindgroups(
                 binding: 1,
                 resource: camera_buffer.as_entire_binding(),
             },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: params_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: orbit_buffer.as_entire_binding(),
+            },
         ],
-        label: Some(format!("bind_group").as_str()),
+        label: Some("bind_group"),
     });
 
     (
         (zoom_uniform, zoom_buffer),
         (camera_uniform, camera_buffer),
+        (params_uniform, params_buffer),
+        orbit_buffer,
         bind_group,
         bind_group_layout,
     )
 }
 
+/// Computes the reference orbit for `camera` and uploads it, if the shader
+/// is going to need it.
+fn write_reference_orbit(
+    queue: &wgpu::Queue,
+    orbit_buffer: &wgpu::Buffer,
+    camera: &Camera,
+    params: &Params,
+) {
+    if params.precision == Precision::Perturbation {
+        let orbit = ReferenceOrbit::new(camera.position(), params);
+        queue.write_buffer(orbit_buffer, 0, &orbit.to_bytes());
+    }
+}
+
+/// A `ReferenceOrbit` along with the camera position and parameters it was
+/// iterated for, so it only gets iterated again once those change.
+struct CachedOrbit {
+    pos: Position,
+    params: Params,
+    orbit: ReferenceOrbit,
+}
+
+fn draw_fractal(
+    encoder: &mut wgpu::CommandEncoder,
+    view: &wgpu::TextureView,
+    render_pipeline: &wgpu::RenderPipeline,
+    bind_group: &wgpu::BindGroup,
+    vertex_buffer: &wgpu::Buffer,
+    index_buffer: &wgpu::Buffer,
+    num_indices: u32,
+) {
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Render Pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color {
+                    r: 0.1,
+                    g: 0.2,
+                    b: 0.3,
+                    a: 1.0,
+                }),
+                store: true,
+            },
+        })],
+        depth_stencil_attachment: None,
+    });
+
+    render_pass.set_pipeline(render_pipeline);
+
+    render_pass.set_bind_group(0, bind_group, &[]);
+
+    render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+    render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+
+    render_pass.draw_indexed(0..num_indices, 0, 0..1);
+}
+
 impl Vertex {
     fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
         wgpu::VertexBufferLayout {
@@ -236,13 +393,17 @@ impl Vertex {
                 wgpu::VertexAttribute {
                     offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
                     shader_location: 1,
-                    format: wgpu::VertexFormat::Float32x3,
+                    format: wgpu::VertexFormat::Float32x2,
                 },
             ],
         }
     }
 }
 
+// How much the zoom grows by each second, so it doesn't depend on the frame
+// rate. About 1.0001 a frame at 60 frames a second
+const ZOOM_PER_SECOND: f32 = 1.006;
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 struct Zoom {
@@ -259,22 +420,63 @@ impl Default for Zoom {
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 struct Vertex {
     position: [f32; 3],
-    real_pos: [f32; 3],
+    // Where on the whole image the vertex is, from -1 to 1 on both axes.
+    // Only differs from `position` when rendering in tiles
+    screen_pos: [f32; 2],
 }
 
-// The camera size multiplier
-// The smaller the better
-// Until floating point prec errors
-const SCALE: f32 = 0.0001;
-
 #[rustfmt::skip]
 const VERTICES: &[Vertex] = &[
-    Vertex { position: [-1.0, -1.0, 0.0], real_pos: [-2.0 * SCALE, -1.125 * SCALE, 2.0 * SCALE] },
-    Vertex { position: [1.0, -1.0, 0.0], real_pos: [2.0 * SCALE, -1.125 * SCALE, 2.0 * SCALE] },
-    Vertex { position: [-1.0, 1.0, 0.0], real_pos: [-2.0 * SCALE, 1.125 * SCALE, 2.0 * SCALE] },
-    Vertex { position: [1.0, 1.0, 0.0], real_pos: [2.0 * SCALE, 1.125 * SCALE, 2.0 * SCALE]},
+    Vertex { position: [-1.0, -1.0, 0.0], screen_pos: [-1.0, -1.0] },
+    Vertex { position: [1.0, -1.0, 0.0], screen_pos: [1.0, -1.0] },
+    Vertex { position: [-1.0, 1.0, 0.0], screen_pos: [-1.0, 1.0] },
+    Vertex { position: [1.0, 1.0, 0.0], screen_pos: [1.0, 1.0]},
 ];
 
+/// The `screen_pos` at `(u, v)`, where `(0, 0)` is the top left of the
+/// screen and `(1, 1)` the bottom right.
+fn screen_point(u: f32, v: f32) -> [f32; 2] {
+    [u * 2.0 - 1.0, 1.0 - v * 2.0]
+}
+
+/// A fullscreen quad that only covers the part of the screen between
+/// `(u0, v0)` and `(u1, v1)`, used to render an image in tiles.
+fn tile_vertices(u0: f32, v0: f32, u1: f32, v1: f32) -> [Vertex; 4] {
+    [
+        Vertex {
+            position: VERTICES[0].position,
+            screen_pos: screen_point(u0, v1),
+        },
+        Vertex {
+            position: VERTICES[1].position,
+            screen_pos: screen_point(u1, v1),
+        },
+        Vertex {
+            position: VERTICES[2].position,
+            screen_pos: screen_point(u0, v0),
+        },
+        Vertex {
+            position: VERTICES[3].position,
+            screen_pos: screen_point(u1, v0),
+        },
+    ]
+}
+
+/// Where the bookmarks are kept, relative to the working directory.
+const BOOKMARKS_PATH: &str = "bookmarks.txt";
+
+/// Where the camera path is kept, relative to the working directory.
+const CAMERA_PATH_FILE: &str = "path.txt";
+
+/// The bookmark slot F1 to F9 stand for.
+fn bookmark_slot(key: VirtualKeyCode) -> Option<u8> {
+    use VirtualKeyCode::*;
+    [F1, F2, F3, F4, F5, F6, F7, F8, F9]
+        .iter()
+        .position(|&k| k == key)
+        .map(|i| i as u8 + 1)
+}
+
 #[rustfmt::skip]
 const INDICES: &[u16] = &[
     0, 1, 2,
@@ -295,9 +497,25 @@ pub struct State {
     bind_group: wgpu::BindGroup,
     zoom_uniform: Zoom,
     zoom_buffer: wgpu::Buffer,
-    camera_uniform: Camera,
+    camera: Camera,
     camera_buffer: wgpu::Buffer,
+    params: Params,
+    params_buffer: wgpu::Buffer,
+    orbit_buffer: wgpu::Buffer,
+    // What's in `orbit_buffer`, see `update_reference_orbit`
+    reference_orbit: Option<CachedOrbit>,
     camera_controller: CameraController,
+    // Whether the mouse looks around, which needs the cursor hidden and kept
+    // in the window
+    cursor_grabbed: bool,
+    // In physical pixels from the top left of the window
+    cursor_pos: PhysicalPosition<f64>,
+    last_update: Instant,
+    modifiers: ModifiersState,
+    bookmarks: Bookmarks,
+    path: CameraPath,
+    // How far into the path playback is, while it's playing
+    playback: Option<Duration>,
 }
 
 impl State {
@@ -305,31 +523,31 @@ impl State {
     pub async fn new(window: Window) -> Self {
         let size = window.inner_size();
 
-        // The instance is a handle to our GPU
-        // Backends::all => Vulkan + Metal + DX12 + Browser WebGPU
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::all(),
-            dx12_shader_compiler: Default::default(),
-        });
+        let instance = create_instance();
 
         let (surface, device, queue, config) = setup(&instance, &size, &window).await;
 
         let (
             (zoom_uniform, zoom_buffer),
-            (camera_uniform, camera_buffer),
+            (_, camera_buffer),
+            (_, params_buffer),
+            orbit_buffer,
             bind_group,
             bind_group_layout,
         ) = generate_bindgroups(&device);
+        let mut camera = Camera::default();
+        camera.set_aspect(size.width, size.height);
 
-        let shader = device.create_shader_module(wgpu::include_wgsl!("shader.wgsl"));
+        let shader = create_shader(&device);
 
         let (vertex_buffer, index_buffer) = create_buffers(&device);
 
-        let render_pipeline = create_render_pipeline(&device, &bind_group_layout, &shader, &config);
+        let render_pipeline =
+            create_render_pipeline(&device, &bind_group_layout, &shader, config.format);
 
         let num_indices = INDICES.len() as u32;
 
-        let camera_controller = CameraController::new(0.05, 0.02, 1.1);
+        let camera_controller = CameraController::new(1.0, 1.0, 4.0);
 
         Self {
             window,
@@ -346,8 +564,25 @@ impl State {
             zoom_uniform,
             zoom_buffer,
             camera_buffer,
-            camera_uniform,
+            camera,
+            params: Params::default(),
+            params_buffer,
+            orbit_buffer,
+            reference_orbit: None,
             camera_controller,
+            cursor_grabbed: false,
+            cursor_pos: PhysicalPosition::default(),
+            last_update: Instant::now(),
+            modifiers: ModifiersState::default(),
+            bookmarks: Bookmarks::load(BOOKMARKS_PATH).unwrap_or_else(|e| {
+                log::warn!("Couldn't load the bookmarks: {e}");
+                Bookmarks::default()
+            }),
+            path: CameraPath::load(CAMERA_PATH_FILE).unwrap_or_else(|e| {
+                log::warn!("Couldn't load the camera path: {e}");
+                CameraPath::default()
+            }),
+            playback: None,
         }
     }
 
@@ -361,18 +596,317 @@ impl State {
             self.config.width = new_size.width;
             self.config.height = new_size.height;
             self.surface.configure(&self.device, &self.config);
+            self.camera.set_aspect(new_size.width, new_size.height);
         }
     }
 
     pub fn input(&mut self, events: &WindowEvent) -> bool {
+        // C needs to know where the camera is, so it can't live in the controllers
+        if let WindowEvent::KeyboardInput {
+            input:
+                KeyboardInput {
+                    state,
+                    virtual_keycode: Some(VirtualKeyCode::C),
+                    ..
+                },
+            ..
+        } = events
+        {
+            if *state == ElementState::Pressed {
+                self.seed_julia_from_camera();
+            }
+            return true;
+        }
+
+        // F1 to F9 recall bookmarks and save them with Ctrl, which needs
+        // the whole state
+        match events {
+            WindowEvent::ModifiersChanged(modifiers) => self.modifiers = *modifiers,
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } => {
+                if let Some(slot) = bookmark_slot(*key) {
+                    if *state == ElementState::Pressed {
+                        if self.modifiers.ctrl() {
+                            self.save_bookmark(slot);
+                        } else {
+                            self.recall_bookmark(slot);
+                        }
+                    }
+                    return true;
+                }
+            }
+            _ => {}
+        }
+
+        // G, Backspace and Y record and play back the camera path
+        if let WindowEvent::KeyboardInput {
+            input:
+                KeyboardInput {
+                    state,
+                    virtual_keycode:
+                        Some(key @ (VirtualKeyCode::G | VirtualKeyCode::Back | VirtualKeyCode::Y)),
+                    ..
+                },
+            ..
+        } = events
+        {
+            if *state == ElementState::Pressed {
+                match key {
+                    VirtualKeyCode::G => self.add_keyframe(),
+                    VirtualKeyCode::Back => self.remove_keyframe(),
+                    _ => self.toggle_playback(),
+                }
+            }
+            return true;
+        }
+
+        // Same for R and right clicks, which march a ray to find the pivot
+        match events {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state,
+                        virtual_keycode: Some(VirtualKeyCode::R),
+                        ..
+                    },
+                ..
+            } => {
+                if *state == ElementState::Pressed {
+                    self.toggle_orbit();
+                }
+                return true;
+            }
+            WindowEvent::CursorMoved { position, .. } => self.cursor_pos = *position,
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Right,
+                ..
+            } => {
+                // The cursor is hidden in the middle of the screen while grabbed
+                let screen_pos = if self.cursor_grabbed {
+                    [0.0, 0.0]
+                } else {
+                    screen_point(
+                        (self.cursor_pos.x / self.size.width as f64) as f32,
+                        (self.cursor_pos.y / self.size.height as f64) as f32,
+                    )
+                };
+                self.orbit_surface_point(screen_pos);
+                return true;
+            }
+            _ => {}
+        }
+
+        // Clicking grabs the cursor for mouse look, Escape lets go of it
+        // before it quits
+        match events {
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } if !self.cursor_grabbed => {
+                self.set_cursor_grabbed(true);
+                return true;
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::Escape),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } if self.cursor_grabbed => {
+                self.set_cursor_grabbed(false);
+                return true;
+            }
+            WindowEvent::Focused(false) => self.set_cursor_grabbed(false),
+            _ => {}
+        }
+
         self.camera_controller.process_events(events)
+            || params::process_events(&mut self.params, events)
+    }
+
+    /// Puts the current view in bookmark `slot` and writes all of them to
+    /// `BOOKMARKS_PATH`.
+    pub fn save_bookmark(&mut self, slot: u8) {
+        self.bookmarks.set(
+            slot,
+            Bookmark {
+                camera: self.camera.clone(),
+                speed: self.camera_controller.speed(),
+                params: self.params.clone(),
+            },
+        );
+        match self.bookmarks.save(BOOKMARKS_PATH) {
+            Ok(()) => log::info!("saved bookmark {slot} to {BOOKMARKS_PATH}"),
+            Err(e) => log::warn!("Couldn't save the bookmarks to {BOOKMARKS_PATH}: {e}"),
+        }
+    }
+
+    /// Flies over to the view in bookmark `slot`, with its parameters.
+    pub fn recall_bookmark(&mut self, slot: u8) {
+        let Some(bookmark) = self.bookmarks.get(slot) else {
+            log::info!("no bookmark {slot}, Ctrl+F{slot} saves one");
+            return;
+        };
+
+        let mut to = bookmark.camera.clone();
+        to.aspect = self.camera.aspect;
+        self.params = bookmark.params.clone();
+        self.camera_controller.set_speed(bookmark.speed);
+        self.camera_controller.fly_to(&self.camera, &to);
+        log::info!("recalled bookmark {slot}");
+    }
+
+    /// Adds where the camera is now to the end of the path and saves it to
+    /// `CAMERA_PATH_FILE`.
+    pub fn add_keyframe(&mut self) {
+        self.path.push(self.camera.clone());
+        log::info!("keyframe {} added", self.path.keyframes.len());
+        self.save_path();
+    }
+
+    /// Takes the last keyframe back off the path.
+    pub fn remove_keyframe(&mut self) {
+        if self.path.keyframes.pop().is_some() {
+            log::info!("{} keyframes left", self.path.keyframes.len());
+            self.save_path();
+        }
+    }
+
+    fn save_path(&self) {
+        if let Err(e) = self.path.save(CAMERA_PATH_FILE) {
+            log::warn!("Couldn't save the camera path to {CAMERA_PATH_FILE}: {e}");
+        }
+    }
+
+    /// Starts flying along the path from the start, or stops where it is.
+    pub fn toggle_playback(&mut self) {
+        if self.playback.take().is_some() {
+            log::info!("playback stopped");
+        } else if self.path.keyframes.len() < 2 {
+            log::info!("the path needs at least 2 keyframes to play, G adds one");
+        } else {
+            self.camera_controller.stop_orbiting();
+            self.playback = Some(Duration::ZERO);
+            log::info!("playing the path, {:?} long", self.path.duration());
+        }
+    }
+
+    /// Switches between flying freely and orbiting around whatever the
+    /// camera is looking at, without moving it.
+    pub fn toggle_orbit(&mut self) {
+        if self.camera_controller.is_orbiting() {
+            self.camera_controller.stop_orbiting();
+            return;
+        }
+
+        let pivot =
+            cpu::surface_point(&self.camera, [0.0, 0.0], &self.params).unwrap_or_else(|| {
+                // Looking past the set, so orbit around a point as far ahead
+                // as the set is
+                let distance = cpu::distance_at(&self.camera, &self.params);
+                let distance = if distance > 0.0 { distance } else { 1.0 };
+                let forward = self.camera.rot()[2];
+                let mut pivot = self.camera.position().clone();
+                pivot.translate(forward.map(|x| (x * distance) as f64));
+                pivot
+            });
+        self.camera_controller.orbit_around(&mut self.camera, pivot);
+    }
+
+    /// Orbits around the surface point under `screen_pos`, if there is one.
+    pub fn orbit_surface_point(&mut self, screen_pos: [f32; 2]) {
+        match cpu::surface_point(&self.camera, screen_pos, &self.params) {
+            Some(pivot) => self.camera_controller.orbit_around(&mut self.camera, pivot),
+            None => log::info!("no surface there to orbit around"),
+        }
+    }
+
+    /// Raw mouse movement, which turns the camera while the cursor is
+    /// grabbed.
+    pub fn mouse_motion(&mut self, delta: (f64, f64)) {
+        // Turning during playback would all happen at once after it
+        if self.cursor_grabbed && self.playback.is_none() {
+            self.camera_controller
+                .process_mouse_motion(delta.0, delta.1);
+        }
+    }
+
+    fn set_cursor_grabbed(&mut self, grabbed: bool) {
+        if grabbed == self.cursor_grabbed {
+            return;
+        }
+
+        let result = if grabbed {
+            // Not every platform can confine the cursor to the window and
+            // not every one can lock it in place, but the raw motion is the
+            // same either way
+            self.window
+                .set_cursor_grab(CursorGrabMode::Confined)
+                .or_else(|_| self.window.set_cursor_grab(CursorGrabMode::Locked))
+        } else {
+            self.window.set_cursor_grab(CursorGrabMode::None)
+        };
+        if let Err(e) = result {
+            log::warn!("Couldn't grab the cursor: {e}");
+        }
+
+        self.window.set_cursor_visible(!grabbed);
+        self.cursor_grabbed = grabbed;
+    }
+
+    /// Switches to Julia mode with the point of quaternion space the camera
+    /// is at as the constant.
+    pub fn seed_julia_from_camera(&mut self) {
+        let pos = cgmath::Vector4::from(self.camera.position().to_f32());
+        self.params.julia_c = cpu::to_quat(pos, &self.params).into();
+        self.params.julia = true;
+        log::info!("julia c: {:?}", self.params.julia_c);
+    }
+
+    pub fn params(&self) -> &Params {
+        &self.params
+    }
+
+    /// Changes the fractal parameters, which take effect on the next frame.
+    pub fn set_params(&mut self, params: Params) {
+        self.params = params;
     }
 
     pub fn update(&mut self) {
-        self.camera_controller
-            .update_camera(&mut self.camera_uniform);
+        let now = Instant::now();
+        let dt = now - self.last_update;
+        self.last_update = now;
+        match &mut self.playback {
+            Some(time) => {
+                *time += dt;
+                self.path.place(&mut self.camera, *time);
+                if *time >= self.path.duration() {
+                    self.playback = None;
+                    log::info!("playback done");
+                }
+            }
+            None => {
+                // The orbit is usually still the one uploaded last frame
+                self.update_reference_orbit();
+                let orbit = self.reference_orbit.as_ref().map(|cached| &cached.orbit);
+                let distance = cpu::distance_with_orbit(&self.camera, orbit, &self.params);
+                self.camera_controller
+                    .update_camera(&mut self.camera, dt, distance);
+            }
+        }
 
-        self.zoom_uniform.zoom *= 1.0001;
+        self.zoom_uniform.zoom *= ZOOM_PER_SECOND.powf(dt.as_secs_f32());
 
         self.queue.write_buffer(
             &self.zoom_buffer,
@@ -382,8 +916,38 @@ impl State {
         self.queue.write_buffer(
             &self.camera_buffer,
             0,
-            bytemuck::cast_slice(&[self.camera_uniform]),
-        )
+            bytemuck::cast_slice(&[CameraUniform::from(&self.camera)]),
+        );
+        self.queue.write_buffer(
+            &self.params_buffer,
+            0,
+            bytemuck::cast_slice(&[ParamsUniform::from(&self.params)]),
+        );
+        self.update_reference_orbit();
+    }
+
+    /// Iterates the reference orbit for the camera and uploads it, if the
+    /// shader is going to need it and the camera or the parameters have
+    /// changed since the last one.
+    fn update_reference_orbit(&mut self) {
+        if self.params.precision != Precision::Perturbation {
+            return;
+        }
+        let up_to_date = self.reference_orbit.as_ref().is_some_and(|cached| {
+            cached.pos == *self.camera.position() && cached.params == self.params
+        });
+        if up_to_date {
+            return;
+        }
+
+        let orbit = ReferenceOrbit::new(self.camera.position(), &self.params);
+        self.queue
+            .write_buffer(&self.orbit_buffer, 0, &orbit.to_bytes());
+        self.reference_orbit = Some(CachedOrbit {
+            pos: self.camera.position().clone(),
+            params: self.params.clone(),
+            orbit,
+        });
     }
 
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
@@ -399,34 +963,15 @@ impl State {
                 label: Some("Render Encoder"),
             });
 
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.1,
-                            g: 0.2,
-                            b: 0.3,
-                            a: 1.0,
-                        }),
-                        store: true,
-                    },
-                })],
-                depth_stencil_attachment: None,
-            });
-
-            render_pass.set_pipeline(&self.render_pipeline);
-
-            render_pass.set_bind_group(0, &self.bind_group, &[]);
-
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-
-            render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
-        }
+        draw_fractal(
+            &mut encoder,
+            &view,
+            &self.render_pipeline,
+            &self.bind_group,
+            &self.vertex_buffer,
+            &self.index_buffer,
+            self.num_indices,
+        );
 
         // submit will accept anything that implements IntoIter
         self.queue.submit(std::iter::once(encoder.finish()));
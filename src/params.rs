@@ -0,0 +1,690 @@
+use std::{fmt, str::FromStr};
+
+use cgmath::{Matrix4, SquareMatrix};
+
+use crate::flip::{self, Flip, FlipSchedule, MAX_FLIPS};
+use winit::event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent};
+
+/// How a pixel gets colored once its ray is done marching.
+///
+/// Every mode other than `Steps` leaves rays that miss the set black.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Shading {
+    /// Red heatmap of how many steps the ray took
+    #[default]
+    Steps,
+    /// Surface normals as RGB
+    Normals,
+    /// Phong lighting
+    Phong,
+    /// Brighter the closer the surface is to the camera
+    Depth,
+    /// Gray scale of how many iterations the surface point took to escape
+    Iterations,
+}
+
+impl Shading {
+    pub const ALL: [Shading; 5] = [
+        Shading::Steps,
+        Shading::Normals,
+        Shading::Phong,
+        Shading::Depth,
+        Shading::Iterations,
+    ];
+
+    /// The mode after this one, wrapping around.
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Shading::Steps => "steps",
+            Shading::Normals => "normals",
+            Shading::Phong => "phong",
+            Shading::Depth => "depth",
+            Shading::Iterations => "iterations",
+        }
+    }
+}
+
+impl fmt::Display for Shading {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Shading {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|shading| shading.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown shading mode {s:?}"))
+    }
+}
+
+/// What the distance estimate tracks as the derivative of the iteration.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Derivative {
+    /// A single quaternion, as if every step were a quaternion
+    /// multiplication. Fast, but ignores the flip and overestimates the
+    /// distance around flipped regions
+    #[default]
+    Quaternion,
+    /// The full 4x4 Jacobian, flip included. Slower, but the ray march
+    /// never oversteps
+    Jacobian,
+}
+
+impl Derivative {
+    pub const ALL: [Derivative; 2] = [Derivative::Quaternion, Derivative::Jacobian];
+
+    /// The mode after this one, wrapping around.
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Derivative::Quaternion => "quaternion",
+            Derivative::Jacobian => "jacobian",
+        }
+    }
+}
+
+impl fmt::Display for Derivative {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Derivative {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|derivative| derivative.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown derivative {s:?}"))
+    }
+}
+
+/// How the distance to the set gets estimated from the iteration.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Estimator {
+    /// `0.5 ln(r) r / |dz|`
+    #[default]
+    Log,
+    /// `0.5 r / |dz|`, which keeps getting bigger further out instead of
+    /// flattening off
+    Linear,
+    /// From finite differences of the escape potential `ln(r) / n^i`, so
+    /// it doesn't need the derivative at all. Much slower
+    Gradient,
+}
+
+impl Estimator {
+    pub const ALL: [Estimator; 3] = [Estimator::Log, Estimator::Linear, Estimator::Gradient];
+
+    /// The estimator after this one, wrapping around.
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Estimator::Log => "log",
+            Estimator::Linear => "linear",
+            Estimator::Gradient => "gradient",
+        }
+    }
+}
+
+impl fmt::Display for Estimator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Estimator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|estimator| estimator.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown estimator {s:?}"))
+    }
+}
+
+/// How precisely the shader tracks positions and iterates.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Precision {
+    /// Plain `f32`, which gets blocky once the camera is very close to the
+    /// surface
+    #[default]
+    Single,
+    /// Emulated double precision for the camera position, the ray and the
+    /// iteration, good for a few more orders of magnitude of zoom but much
+    /// slower. Only powers of 2 iterate in double precision, other powers
+    /// go through single precision polar form
+    Double,
+    /// Iterates the camera position in arbitrary precision on the CPU, and
+    /// every other point as its `f32` offset from that, see `perturbation`.
+    /// Goes as deep as `f32` offsets can, as long as the camera is about
+    /// as close to the surface as the details it looks at. The derivative
+    /// still overflows `f32` about 1e-18 from the surface, which is as close
+    /// as it gets. Only powers of 2 get perturbed, other powers iterate in
+    /// single precision
+    Perturbation,
+}
+
+impl Precision {
+    pub const ALL: [Precision; 3] = [
+        Precision::Single,
+        Precision::Double,
+        Precision::Perturbation,
+    ];
+
+    /// The precision after this one, wrapping around.
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Precision::Single => "single",
+            Precision::Double => "double",
+            Precision::Perturbation => "perturbation",
+        }
+    }
+}
+
+impl fmt::Display for Precision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Precision {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|precision| precision.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown precision {s:?}"))
+    }
+}
+
+/// Fractal, ray marching and lighting settings that the shader reads at
+/// runtime instead of having them baked in as `const`s.
+///
+/// Build one with `..Params::default()` to only change some of them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Params {
+    pub max_iterations: u32,
+    /// |z|² above which a point counts as escaped
+    pub bailout: f32,
+    pub max_steps: u32,
+    /// A ray that gets this close to the set counts as a hit
+    pub min_distance: f32,
+    /// A ray that gets this far from the set counts as a miss
+    pub max_distance: f32,
+    /// Offset for the finite differences in `normals` and the gradient
+    /// estimator
+    pub delta: f32,
+    pub light_pos: [f32; 3],
+    pub light_intensity: f32,
+    pub ambient_light: [f32; 3],
+    /// Phong ambient color
+    pub k_a: [f32; 3],
+    /// Phong diffuse color
+    pub k_d: [f32; 3],
+    /// Phong specular color
+    pub k_s: [f32; 3],
+    /// Phong shininess coefficient
+    pub shininess: f32,
+    pub shading: Shading,
+    /// Where the 3D slice sits along the fourth axis, before rotating
+    pub slice_w: f32,
+    /// Rotates the slicing hyperplane through 4D space, column major. A
+    /// point `(x, y, z, w)` is at `slice_rotation * (x, y, z, w + slice_w)`
+    pub slice_rotation: [[f32; 4]; 4],
+    /// Render the Julia set for `julia_c` instead of the mandelbrot set
+    pub julia: bool,
+    /// The constant added every iteration in Julia mode, real part first
+    pub julia_c: [f32; 4],
+    /// Maps applied to `z` between iterations, used in turn. Only the first
+    /// `MAX_FLIPS` are used and an empty list means no flip at all
+    pub flips: Vec<Flip>,
+    pub flip_schedule: FlipSchedule,
+    /// The `n` in `z^n + c`. Doesn't have to be a whole number
+    pub power: f32,
+    pub derivative: Derivative,
+    pub estimator: Estimator,
+    /// Every distance estimate gets multiplied by this. Below 1 the ray
+    /// takes smaller steps, trading speed for fewer overstepping artifacts
+    pub fudge: f32,
+    pub precision: Precision,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Params {
+            max_iterations: 200,
+            bailout: 10.0,
+            max_steps: 500,
+            min_distance: 0.00001,
+            max_distance: 100.0,
+            delta: 0.001,
+            light_pos: [-50.0, 50.0, 50.0],
+            light_intensity: 0.4,
+            ambient_light: [0.1, 0.1, 0.1],
+            k_a: [0.2, 0.2, 0.2],
+            k_d: [0.7, 0.2, 0.2],
+            k_s: [1.0, 1.0, 1.0],
+            shininess: 10.0,
+            shading: Shading::default(),
+            slice_w: 0.0,
+            slice_rotation: Matrix4::identity().into(),
+            julia: false,
+            julia_c: [-0.2, 0.6, 0.2, 0.0],
+            flips: vec![Flip::default()],
+            flip_schedule: FlipSchedule::default(),
+            power: 2.0,
+            derivative: Derivative::default(),
+            estimator: Estimator::default(),
+            fudge: 1.0,
+            precision: Precision::default(),
+        }
+    }
+}
+
+impl Params {
+    /// Every parameter as a `(name, value)` pair, in a form `set` parses
+    /// back exactly.
+    pub fn entries(&self) -> Vec<(&'static str, String)> {
+        let flips: Vec<String> = self.flips.iter().map(Flip::to_string).collect();
+        vec![
+            ("max_iterations", self.max_iterations.to_string()),
+            ("bailout", self.bailout.to_string()),
+            ("max_steps", self.max_steps.to_string()),
+            ("min_distance", self.min_distance.to_string()),
+            ("max_distance", self.max_distance.to_string()),
+            ("delta", self.delta.to_string()),
+            ("light_pos", join(&self.light_pos)),
+            ("light_intensity", self.light_intensity.to_string()),
+            ("ambient_light", join(&self.ambient_light)),
+            ("k_a", join(&self.k_a)),
+            ("k_d", join(&self.k_d)),
+            ("k_s", join(&self.k_s)),
+            ("shininess", self.shininess.to_string()),
+            ("shading", self.shading.to_string()),
+            ("slice_w", self.slice_w.to_string()),
+            ("slice_rotation", join(self.slice_rotation.as_flattened())),
+            ("julia", self.julia.to_string()),
+            ("julia_c", join(&self.julia_c)),
+            // Flips have commas of their own
+            ("flips", flips.join(";")),
+            ("flip_schedule", self.flip_schedule.to_string()),
+            ("power", self.power.to_string()),
+            ("derivative", self.derivative.to_string()),
+            ("estimator", self.estimator.to_string()),
+            ("fudge", self.fudge.to_string()),
+            ("precision", self.precision.to_string()),
+        ]
+    }
+
+    /// Sets the parameter `name` from a value written like `entries`
+    /// writes it.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        match name {
+            "max_iterations" => self.max_iterations = parse(value)?,
+            "bailout" => self.bailout = parse(value)?,
+            "max_steps" => self.max_steps = parse(value)?,
+            "min_distance" => self.min_distance = parse(value)?,
+            "max_distance" => self.max_distance = parse(value)?,
+            "delta" => self.delta = parse(value)?,
+            "light_pos" => self.light_pos = parse_array(value)?,
+            "light_intensity" => self.light_intensity = parse(value)?,
+            "ambient_light" => self.ambient_light = parse_array(value)?,
+            "k_a" => self.k_a = parse_array(value)?,
+            "k_d" => self.k_d = parse_array(value)?,
+            "k_s" => self.k_s = parse_array(value)?,
+            "shininess" => self.shininess = parse(value)?,
+            "shading" => self.shading = parse(value)?,
+            "slice_w" => self.slice_w = parse(value)?,
+            "slice_rotation" => {
+                let entries: [f32; 16] = parse_array(value)?;
+                for (column, entries) in self.slice_rotation.iter_mut().zip(entries.chunks(4)) {
+                    column.copy_from_slice(entries);
+                }
+            }
+            "julia" => self.julia = parse(value)?,
+            "julia_c" => self.julia_c = parse_array(value)?,
+            "flips" => {
+                self.flips = value
+                    .split(';')
+                    .map(str::trim)
+                    .filter(|flip| !flip.is_empty())
+                    .map(str::parse)
+                    .collect::<Result<_, _>>()?
+            }
+            "flip_schedule" => self.flip_schedule = parse(value)?,
+            "power" => self.power = parse(value)?,
+            "derivative" => self.derivative = parse(value)?,
+            "estimator" => self.estimator = parse(value)?,
+            "fudge" => self.fudge = parse(value)?,
+            "precision" => self.precision = parse(value)?,
+            _ => return Err(format!("unknown parameter {name:?}")),
+        }
+        Ok(())
+    }
+}
+
+fn join(values: &[f32]) -> String {
+    let values: Vec<String> = values.iter().map(f32::to_string).collect();
+    values.join(",")
+}
+
+fn parse<T: FromStr>(s: &str) -> Result<T, String>
+where
+    T::Err: fmt::Display,
+{
+    s.trim().parse().map_err(|e| format!("{s:?}: {e}"))
+}
+
+/// Parses exactly `N` comma separated values.
+fn parse_array<const N: usize, T: FromStr>(s: &str) -> Result<[T; N], String>
+where
+    T::Err: fmt::Display,
+{
+    let values = s.split(',').map(parse).collect::<Result<Vec<T>, _>>()?;
+    values
+        .try_into()
+        .map_err(|v: Vec<T>| format!("expected {N} numbers but got {}", v.len()))
+}
+
+// Must be the same as shader.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, Default)]
+pub(crate) struct ParamsUniform {
+    max_iterations: u32,
+    bailout: f32,
+    max_steps: u32,
+    min_distance: f32,
+    max_distance: f32,
+    delta: f32,
+    light_intensity: f32,
+    shininess: f32,
+    light_pos: [f32; 3],
+    _padding: u32,
+    ambient_light: [f32; 3],
+    _padding2: u32,
+    k_a: [f32; 3],
+    _padding3: u32,
+    k_d: [f32; 3],
+    _padding4: u32,
+    k_s: [f32; 3],
+    shading: u32,
+    slice_rotation: [[f32; 4]; 4],
+    slice_w: f32,
+    julia: u32,
+    _padding5: [u32; 2],
+    julia_c: [f32; 4],
+    flips: [[[f32; 4]; 4]; MAX_FLIPS],
+    flip_count: u32,
+    // Every how many iterations to flip, 0 meaning only before the first
+    flip_every: u32,
+    power: f32,
+    derivative: u32,
+    estimator: u32,
+    fudge: f32,
+    float_precision: u32,
+    // Always 1, but the shader compiler can't know that. See `Df4` in the
+    // shader
+    one: f32,
+}
+
+impl From<&Params> for ParamsUniform {
+    fn from(params: &Params) -> Self {
+        let mut flips = [[[0.0; 4]; 4]; MAX_FLIPS];
+        for (uniform, flip) in flips.iter_mut().zip(&params.flips) {
+            *uniform = flip.matrix;
+        }
+
+        Self {
+            max_iterations: params.max_iterations,
+            bailout: params.bailout,
+            max_steps: params.max_steps,
+            min_distance: params.min_distance,
+            max_distance: params.max_distance,
+            delta: params.delta,
+            light_intensity: params.light_intensity,
+            shininess: params.shininess,
+            light_pos: params.light_pos,
+            ambient_light: params.ambient_light,
+            k_a: params.k_a,
+            k_d: params.k_d,
+            k_s: params.k_s,
+            shading: params.shading as u32,
+            slice_rotation: params.slice_rotation,
+            slice_w: params.slice_w,
+            julia: params.julia as u32,
+            julia_c: params.julia_c,
+            flips,
+            flip_count: params.flips.len().min(MAX_FLIPS) as u32,
+            flip_every: params.flip_schedule.period(),
+            power: params.power,
+            derivative: params.derivative as u32,
+            estimator: params.estimator as u32,
+            fudge: params.fudge,
+            float_precision: params.precision as u32,
+            one: 1.0,
+            ..Default::default()
+        }
+    }
+}
+
+// How much one key press moves a component of the Julia constant
+const JULIA_STEP: f32 = 0.01;
+
+// How much one key press changes the power
+const POWER_STEP: f32 = 0.1;
+
+// How much one key press changes the fudge factor
+const FUDGE_STEP: f32 = 0.05;
+
+/// Steps the commonly tweaked parameters on key presses.
+///
+/// `[` and `]` change the iterations, `-` and `=` the march steps,
+/// `,` and `.` how close a ray has to get to count as a hit, Tab
+/// cycles through the shading modes, Page Up and Page Down move the
+/// slice along the fourth axis, V toggles Julia mode, 2 to 9 nudge the
+/// components of the Julia constant down and up, F cycles through the flip
+/// presets, T through the flip schedules, Home and End change the power and B
+/// switches how the distance estimate's derivative is tracked. N cycles
+/// through the distance estimators and Delete and Insert change the fudge
+/// factor and P cycles through single, double and perturbation precision.
+pub fn process_events(params: &mut Params, event: &WindowEvent) -> bool {
+    match event {
+        WindowEvent::KeyboardInput {
+            input:
+                KeyboardInput {
+                    state,
+                    virtual_keycode: Some(keycode),
+                    ..
+                },
+            ..
+        } => {
+            let is_pressed = *state == ElementState::Pressed;
+            let handled = match keycode {
+                VirtualKeyCode::LBracket => {
+                    if is_pressed {
+                        params.max_iterations = params.max_iterations.saturating_sub(10).max(1);
+                    }
+                    true
+                }
+                VirtualKeyCode::RBracket => {
+                    if is_pressed {
+                        params.max_iterations += 10;
+                    }
+                    true
+                }
+                VirtualKeyCode::Minus => {
+                    if is_pressed {
+                        params.max_steps = params.max_steps.saturating_sub(50).max(1);
+                    }
+                    true
+                }
+                VirtualKeyCode::Equals => {
+                    if is_pressed {
+                        params.max_steps += 50;
+                    }
+                    true
+                }
+                VirtualKeyCode::Comma => {
+                    if is_pressed {
+                        params.min_distance *= 2.0;
+                    }
+                    true
+                }
+                VirtualKeyCode::Period => {
+                    if is_pressed {
+                        params.min_distance *= 0.5;
+                    }
+                    true
+                }
+                VirtualKeyCode::Tab => {
+                    if is_pressed {
+                        params.shading = params.shading.next();
+                    }
+                    true
+                }
+                VirtualKeyCode::PageUp => {
+                    if is_pressed {
+                        params.slice_w += 0.05;
+                    }
+                    true
+                }
+                VirtualKeyCode::PageDown => {
+                    if is_pressed {
+                        params.slice_w -= 0.05;
+                    }
+                    true
+                }
+                VirtualKeyCode::V => {
+                    if is_pressed {
+                        params.julia = !params.julia;
+                    }
+                    true
+                }
+                VirtualKeyCode::Key2
+                | VirtualKeyCode::Key3
+                | VirtualKeyCode::Key4
+                | VirtualKeyCode::Key5
+                | VirtualKeyCode::Key6
+                | VirtualKeyCode::Key7
+                | VirtualKeyCode::Key8
+                | VirtualKeyCode::Key9 => {
+                    if is_pressed {
+                        // 2 and 3 are the real part, 4 and 5 i, 6 and 7 j, 8 and 9 k
+                        let key = *keycode as usize - VirtualKeyCode::Key2 as usize;
+                        let step = if key.is_multiple_of(2) {
+                            -JULIA_STEP
+                        } else {
+                            JULIA_STEP
+                        };
+                        params.julia_c[key / 2] += step;
+                    }
+                    true
+                }
+                VirtualKeyCode::F => {
+                    if is_pressed {
+                        let current = params.flips.first().map(Flip::to_string);
+                        let next = flip::PRESETS
+                            .iter()
+                            .position(|preset| Some(*preset) == current.as_deref())
+                            .map_or(0, |i| (i + 1) % flip::PRESETS.len());
+                        params.flips = vec![flip::PRESETS[next].parse().unwrap()];
+                    }
+                    true
+                }
+                VirtualKeyCode::T => {
+                    if is_pressed {
+                        params.flip_schedule = params.flip_schedule.next();
+                    }
+                    true
+                }
+                VirtualKeyCode::Home => {
+                    if is_pressed {
+                        params.power -= POWER_STEP;
+                    }
+                    true
+                }
+                VirtualKeyCode::End => {
+                    if is_pressed {
+                        params.power += POWER_STEP;
+                    }
+                    true
+                }
+                VirtualKeyCode::B => {
+                    if is_pressed {
+                        params.derivative = params.derivative.next();
+                    }
+                    true
+                }
+                VirtualKeyCode::N => {
+                    if is_pressed {
+                        params.estimator = params.estimator.next();
+                    }
+                    true
+                }
+                VirtualKeyCode::Delete => {
+                    if is_pressed {
+                        params.fudge = (params.fudge - FUDGE_STEP).max(FUDGE_STEP);
+                    }
+                    true
+                }
+                VirtualKeyCode::Insert => {
+                    if is_pressed {
+                        params.fudge += FUDGE_STEP;
+                    }
+                    true
+                }
+                VirtualKeyCode::P => {
+                    if is_pressed {
+                        params.precision = params.precision.next();
+                    }
+                    true
+                }
+                _ => false,
+            };
+
+            if handled && is_pressed {
+                log::info!(
+                    "iterations: {}, steps: {}, min distance: {:e}, shading: {}, slice w: {:.2}, julia: {} {:?}, flips: {:?} {}, power: {:.2}, derivative: {}, estimator: {} x{:.2}, precision: {}",
+                    params.max_iterations,
+                    params.max_steps,
+                    params.min_distance,
+                    params.shading,
+                    params.slice_w,
+                    params.julia,
+                    params.julia_c,
+                    params.flips.iter().map(Flip::to_string).collect::<Vec<_>>(),
+                    params.flip_schedule,
+                    params.power,
+                    params.derivative,
+                    params.estimator,
+                    params.fudge,
+                    params.precision
+                );
+            }
+            handled
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,274 @@
+//! Camera paths to fly through, made of keyframes taken from the live
+//! camera and kept in a text file like bookmarks are:
+//!
+//! ```text
+//! [1]
+//! pos = 0,0,-3,0
+//! orientation_left = 1,0,0,0
+//! orientation_right = 1,0,0,0
+//! fov = 90
+//! duration = 2
+//!
+//! [2]
+//! ...
+//! ```
+//!
+//! `duration` is how many seconds it takes to get from a keyframe to the
+//! next one. The numbers in the headers are only there to read by, the
+//! keyframes play in the order they're in.
+
+use std::{fmt, fs, io, path::Path, str::FromStr, time::Duration};
+
+use crate::{
+    bookmark::{parse_f32, parse_sections, set_camera, write_camera},
+    Camera,
+};
+
+/// How long a segment takes unless it's changed.
+pub const DEFAULT_SEGMENT_TIME: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Keyframe {
+    pub camera: Camera,
+    /// How long it takes to get from this keyframe to the next one. The
+    /// last keyframe's is unused.
+    pub duration: Duration,
+}
+
+/// Keyframes the camera goes through one after the other, on a
+/// Catmull-Rom spline and turning at a constant rate in between.
+///
+/// Where the camera is only depends on the time, so playing a path back
+/// live and rendering it frame by frame see the same.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CameraPath {
+    pub keyframes: Vec<Keyframe>,
+}
+
+impl CameraPath {
+    /// Adds a keyframe at the end, `DEFAULT_SEGMENT_TIME` after the last
+    /// one.
+    pub fn push(&mut self, camera: Camera) {
+        self.keyframes.push(Keyframe {
+            camera,
+            duration: DEFAULT_SEGMENT_TIME,
+        });
+    }
+
+    /// How long it takes to go through the whole path.
+    pub fn duration(&self) -> Duration {
+        match self.keyframes.split_last() {
+            Some((_, rest)) => rest.iter().map(|keyframe| keyframe.duration).sum(),
+            None => Duration::ZERO,
+        }
+    }
+
+    /// Moves `camera` to where it is `time` into the path, leaving its
+    /// aspect ratio alone. After the end it's at the last keyframe, and
+    /// with no keyframes it stays put.
+    pub fn place(&self, camera: &mut Camera, time: Duration) {
+        let Some(last) = self.keyframes.last() else {
+            return;
+        };
+
+        // The segment `time` is in, and how far along it
+        let mut start = Duration::ZERO;
+        let segment = self.keyframes[..self.keyframes.len() - 1]
+            .iter()
+            .position(|keyframe| {
+                start += keyframe.duration;
+                time < start
+            });
+        let Some(i) = segment else {
+            camera.set_position(last.camera.position().clone());
+            camera.set_orientation(last.camera.orientation());
+            camera.fov = last.camera.fov;
+            return;
+        };
+        let (from, to) = (&self.keyframes[i], &self.keyframes[i + 1]);
+        let start = start - from.duration;
+        let d = from.duration.as_secs_f64();
+        let u = (time.saturating_sub(start).as_secs_f64() / d).min(1.0);
+
+        // The offsets are rounded to f64, which deep zooms need more digits
+        // than, so they're from whichever end is closer
+        let base = if u < 0.5 { &from.camera } else { &to.camera }.position();
+        let offset = |j: usize| base.offset_to(self.keyframes[j].camera.position());
+        let (p1, p2) = (offset(i), offset(i + 1));
+
+        // Catmull-Rom tangents in units per second, so they stay smooth
+        // across segments that take different times. The ends have no
+        // neighbour on one side and head straight for the next keyframe
+        let tangent = |j: usize| {
+            let before = j.checked_sub(1).unwrap_or(j);
+            let after = (j + 1).min(self.keyframes.len() - 1);
+            let time: f64 = self.keyframes[before..after]
+                .iter()
+                .map(|keyframe| keyframe.duration.as_secs_f64())
+                .sum();
+            let (a, b) = (offset(before), offset(after));
+            [0, 1, 2, 3].map(|k| (b[k] - a[k]) / time)
+        };
+        let (m1, m2) = (tangent(i), tangent(i + 1));
+
+        // Cubic Hermite basis
+        let h00 = 2.0 * u * u * u - 3.0 * u * u + 1.0;
+        let h10 = u * u * u - 2.0 * u * u + u;
+        let h01 = -2.0 * u * u * u + 3.0 * u * u;
+        let h11 = u * u * u - u * u;
+        let mut pos = base.clone();
+        pos.translate(
+            [0, 1, 2, 3].map(|k| h00 * p1[k] + h10 * d * m1[k] + h01 * p2[k] + h11 * d * m2[k]),
+        );
+
+        let u = u as f32;
+        camera.set_position(pos);
+        camera.set_orientation(from.camera.orientation().slerp(to.camera.orientation(), u));
+        camera.fov = from.camera.fov + (to.camera.fov - from.camera.fov) * u;
+    }
+
+    /// Reads the path in `path`, or an empty one if there's no file there
+    /// yet.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        match fs::read_to_string(path) {
+            Ok(text) => text.parse().map_err(|e| format!("{}: {e}", path.display())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(format!("{}: {e}", path.display())),
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+}
+
+impl fmt::Display for CameraPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, keyframe) in self.keyframes.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            writeln!(f, "[{}]", i + 1)?;
+            write_camera(f, &keyframe.camera)?;
+            writeln!(f, "duration = {}", keyframe.duration.as_secs_f32())?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses the format `Display` writes.
+impl FromStr for CameraPath {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let keyframes = parse_sections(
+            s,
+            |_| {
+                Ok(Keyframe {
+                    camera: Camera::default(),
+                    duration: DEFAULT_SEGMENT_TIME,
+                })
+            },
+            |keyframe, key, value| match key {
+                "duration" => {
+                    keyframe.duration = Duration::try_from_secs_f32(parse_f32(value)?)
+                        .map_err(|e| format!("{value:?}: {e}"))?;
+                    Ok(())
+                }
+                _ => set_camera(&mut keyframe.camera, key, value),
+            },
+        )?;
+        Ok(Self { keyframes })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{InnerSpace, Matrix4, Vector4};
+
+    fn assert_close(a: [f32; 4], b: [f32; 4]) {
+        let diff = Vector4::from(a) - Vector4::from(b);
+        assert!(diff.magnitude() < 1e-5, "{a:?} != {b:?}");
+    }
+
+    fn path(cameras: impl IntoIterator<Item = (Camera, f32)>) -> CameraPath {
+        CameraPath {
+            keyframes: cameras
+                .into_iter()
+                .map(|(camera, duration)| Keyframe {
+                    camera,
+                    duration: Duration::from_secs_f32(duration),
+                })
+                .collect(),
+        }
+    }
+
+    fn at(path: &CameraPath, time: f32) -> Camera {
+        let mut camera = Camera::default();
+        path.place(&mut camera, Duration::from_secs_f32(time));
+        camera
+    }
+
+    #[test]
+    fn goes_through_every_keyframe_on_time() {
+        let path = path([
+            (Camera::new([0.0, 0.0, -3.0], 0.0, 0.0), 1.0),
+            (Camera::new([1.0, 0.5, -2.0], 0.5, 0.2).with_fov(1.0), 3.0),
+            (
+                Camera::new([0.0, 1.0, -1.0], -0.5, 0.0).with_w_angles([0.3, 0.0, 0.0]),
+                0.5,
+            ),
+            (Camera::new([-1.0, 0.0, -2.0], 0.0, -0.3), 2.0),
+        ]);
+        assert_eq!(path.duration(), Duration::from_secs_f32(4.5));
+
+        for (keyframe, time) in path.keyframes.iter().zip([0.0, 1.0, 4.0, 4.5]) {
+            let camera = at(&path, time);
+            assert_eq!(camera.position(), keyframe.camera.position());
+            let [a, b] = [&camera, &keyframe.camera].map(|c| Matrix4::from(c.orientation()));
+            for i in 0..4 {
+                assert_close(a[i].into(), b[i].into());
+            }
+            assert!((camera.fov - keyframe.camera.fov).abs() < 1e-6);
+        }
+
+        // And stays at the end
+        assert_eq!(
+            at(&path, 10.0).position(),
+            path.keyframes[3].camera.position()
+        );
+    }
+
+    #[test]
+    fn evenly_spaced_keyframes_are_flown_through_at_a_constant_speed() {
+        let path = path((0..4).map(|i| (Camera::new([i as f32, 0.0, 0.0], 0.0, 0.0), 2.0)));
+
+        for time in [0.3, 1.0, 2.5, 3.7, 5.9] {
+            assert_close(
+                at(&path, time).position().to_f32(),
+                [time / 2.0, 0.0, 0.0, 0.0],
+            );
+        }
+    }
+
+    #[test]
+    fn paths_round_trip_through_text() {
+        let path = path([
+            (
+                Camera::new([0.1, -0.25, -1.5], 0.3, -0.2).with_roll(0.1),
+                1.5,
+            ),
+            (Camera::default().with_w_angles([0.2, 0.0, -0.4]), 2.0),
+        ]);
+
+        let parsed: CameraPath = path.to_string().parse().unwrap();
+        assert_eq!(parsed.keyframes.len(), 2);
+        for (a, b) in path.keyframes.iter().zip(&parsed.keyframes) {
+            assert_eq!(a.camera.position(), b.camera.position());
+            assert_eq!(a.camera.orientation().normalize(), b.camera.orientation());
+            assert_eq!(a.duration, b.duration);
+        }
+    }
+}
@@ -0,0 +1,244 @@
+//! Perturbation theory, for zooming in further than even `Df64` allows.
+//!
+//! One point, the camera position, gets iterated in arbitrary precision on
+//! the CPU. The orbit it takes is rounded to `f32` and handed to the shader,
+//! which only iterates how far every other point `Z + δ` is from it:
+//!
+//! ```text
+//! (Z + δ)² + C + δc = Z² + C + (Zδ + δZ + δ² + δc)
+//! ```
+//!
+//! The part in brackets is the next `δ`. It never involves `Z + δ` itself,
+//! so `δ` keeps all of its precision however small it is. Flips are linear,
+//! so they act on `Z` and `δ` separately.
+//!
+//! When the reference escapes, or `Z + δ` gets smaller than `δ` and `δ`
+//! would lose precision, the point carries on from a second orbit that
+//! starts at 0 with `δ = Z + δ`. That orbit has to flip at the same
+//! iterations as the point, see `rebase_period`.
+
+use crate::{
+    flip::{FlipSchedule, MAX_FLIPS},
+    position::{real, Position, Real},
+    Params,
+};
+
+/// The most points of each orbit the shader gets. Points that need more
+/// iterations than that rebase at the end.
+pub const MAX_ORBIT_LEN: usize = 1024;
+
+/// A quaternion with `Real` parts, real part first.
+type RealQuaternion = [Real; 4];
+
+/// The orbits of the reference point, rounded to `f32` for the shader.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReferenceOrbit {
+    /// `z` before every iteration's flip, starting at the camera position
+    /// and ending once it escapes
+    pub main: Vec<[f32; 4]>,
+    /// The same for a `z` that starts at 0, which points rebase to
+    pub rebase: Vec<[f32; 4]>,
+    /// Points only rebase before iterations that are a multiple of this
+    pub rebase_period: u32,
+}
+
+impl ReferenceOrbit {
+    /// Iterates the point of quaternion space that `pos` is at.
+    pub fn new(pos: &Position, params: &Params) -> Self {
+        let [x, y, z, w] = pos.coords().clone();
+        let start = mat_mul(
+            params.slice_rotation,
+            [x, y, z, w + real(params.slice_w as f64)],
+        );
+        let c = if params.julia {
+            params.julia_c.map(|x| real(x as f64))
+        } else {
+            start.clone()
+        };
+
+        // After the first iteration `Once` never flips again
+        let rebase_schedule = match params.flip_schedule {
+            FlipSchedule::Once => None,
+            schedule => Some(schedule),
+        };
+
+        Self {
+            main: orbit(start, &c, Some(params.flip_schedule), params),
+            rebase: orbit([0.0; 4].map(real), &c, rebase_schedule, params),
+            rebase_period: rebase_period(params),
+        }
+    }
+
+    /// What the shader's `orbit` storage buffer holds.
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let header = OrbitHeader {
+            main_len: self.main.len() as u32,
+            rebase_len: self.rebase.len() as u32,
+            rebase_period: self.rebase_period,
+            ..Default::default()
+        };
+
+        let mut bytes = bytemuck::bytes_of(&header).to_vec();
+        bytes.extend_from_slice(bytemuck::cast_slice(&self.main));
+        bytes.extend_from_slice(bytemuck::cast_slice(&self.rebase));
+        bytes
+    }
+}
+
+// Must be the same as `ReferenceOrbit` in shader.wgsl, the main and then the
+// rebase orbit follow it
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, Default)]
+struct OrbitHeader {
+    main_len: u32,
+    rebase_len: u32,
+    rebase_period: u32,
+    _padding: u32,
+}
+
+/// Size of the buffer `ReferenceOrbit::to_bytes` gets written to.
+pub(crate) const ORBIT_BUFFER_SIZE: usize =
+    std::mem::size_of::<OrbitHeader>() + 2 * MAX_ORBIT_LEN * std::mem::size_of::<[f32; 4]>();
+
+/// Every how many iterations a point can rebase. Rebasing starts the
+/// rebase orbit over, so that has to be somewhere the flips start over too.
+fn rebase_period(params: &Params) -> u32 {
+    let flip_count = params.flips.len().min(MAX_FLIPS) as u32;
+    match params.flip_schedule.period() {
+        _ if flip_count == 0 => 1,
+        // Rebasing only ever happens after the only flip
+        0 => 1,
+        period => period * flip_count,
+    }
+}
+
+/// `z` before every flip while iterating `z² + c`, until it escapes.
+fn orbit(
+    mut z: RealQuaternion,
+    c: &RealQuaternion,
+    schedule: Option<FlipSchedule>,
+    params: &Params,
+) -> Vec<[f32; 4]> {
+    let flips = &params.flips[..params.flips.len().min(MAX_FLIPS)];
+    let mut flips_done = 0;
+    let mut points = vec![to_f32(&z)];
+
+    // One more point than `mandelbrot` has iterations, as every iteration
+    // needs the point after it
+    for iteration in 0..=params.max_iterations {
+        if points.len() == MAX_ORBIT_LEN {
+            break;
+        }
+
+        if !flips.is_empty() && schedule.is_some_and(|s| s.applies(iteration)) {
+            z = mat_mul(flips[flips_done % flips.len()].matrix, z);
+            flips_done += 1;
+        }
+
+        let square = mul(&z, &z);
+        z = [0, 1, 2, 3].map(|i| &square[i] + &c[i]);
+
+        let point = to_f32(&z);
+        points.push(point);
+        if point.iter().map(|x| x * x).sum::<f32>() > params.bailout {
+            break;
+        }
+    }
+
+    points
+}
+
+fn to_f32(q: &RealQuaternion) -> [f32; 4] {
+    q.each_ref().map(|x| x.to_f32().value())
+}
+
+/// `matrix * v`, with `matrix` column major.
+fn mat_mul(matrix: [[f32; 4]; 4], v: RealQuaternion) -> RealQuaternion {
+    [0, 1, 2, 3].map(|row| {
+        (0..4)
+            .map(|col| real(matrix[col][row] as f64) * &v[col])
+            .fold(real(0.0), |sum, x| sum + x)
+    })
+}
+
+/// Same as `quaternion_mul` in the shader.
+fn mul(a: &RealQuaternion, b: &RealQuaternion) -> RealQuaternion {
+    let [a0, a1, a2, a3] = a;
+    let [b0, b1, b2, b3] = b;
+    [
+        a0 * b0 - a1 * b1 - a2 * b2 - a3 * b3,
+        a1 * b0 + a0 * b1 + a2 * b3 - a3 * b2,
+        a0 * b2 - a1 * b3 + a2 * b0 + a3 * b1,
+        a0 * b3 + a1 * b2 - a2 * b1 + a3 * b0,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cpu, flip::Flip, Quaternion};
+
+    #[test]
+    fn main_orbit_matches_the_f32_iteration() {
+        let params = Params {
+            flip_schedule: FlipSchedule::EveryNth(2),
+            max_iterations: 10,
+            ..Params::default()
+        };
+        let pos = [-0.3f32, 0.1, 0.05, 0.2];
+        let orbit = ReferenceOrbit::new(&pos.into(), &params);
+
+        let mut z = cpu::to_quat(pos.into(), &params);
+        let flip = cgmath::Matrix4::from(params.flips[0].matrix);
+        for (iteration, point) in orbit.main.iter().enumerate().skip(1) {
+            if params.flip_schedule.applies(iteration as u32 - 1) {
+                z = (flip * cgmath::Vector4::from(z)).into();
+            }
+            z = z * z + cpu::to_quat(pos.into(), &params);
+            assert!((z - Quaternion::from(*point)).norm() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn orbits_stop_once_they_escape() {
+        let orbit = ReferenceOrbit::new(&[1.0f32, 1.0, 0.0].into(), &Params::default());
+        let last = *orbit.main.last().unwrap();
+        assert!(Quaternion::from(last).norm_squared() > Params::default().bailout);
+        assert!(orbit.main.len() < 5);
+    }
+
+    #[test]
+    fn rebase_orbit_starts_at_zero() {
+        let orbit = ReferenceOrbit::new(&[-0.3f32, 0.1, 0.05].into(), &Params::default());
+        assert_eq!(orbit.rebase[0], [0.0; 4]);
+        assert_eq!(orbit.rebase[1], orbit.main[0]);
+    }
+
+    #[test]
+    fn rebase_period_covers_all_the_flips() {
+        let flips: Vec<Flip> = vec!["x,-z,-y,w".parse().unwrap(), "x,y,-w,z".parse().unwrap()];
+        let period = |flips: &[Flip], flip_schedule| {
+            rebase_period(&Params {
+                flips: flips.to_vec(),
+                flip_schedule,
+                ..Params::default()
+            })
+        };
+
+        assert_eq!(period(&flips, FlipSchedule::Once), 1);
+        assert_eq!(period(&flips, FlipSchedule::EveryIteration), 2);
+        assert_eq!(period(&flips, FlipSchedule::EveryNth(3)), 6);
+        assert_eq!(period(&[], FlipSchedule::EveryNth(3)), 1);
+    }
+
+    #[test]
+    fn bytes_fit_the_buffer() {
+        let params = Params {
+            max_iterations: 10 * MAX_ORBIT_LEN as u32,
+            ..Params::default()
+        };
+        let orbit = ReferenceOrbit::new(&[0.0f32; 3].into(), &params);
+        assert_eq!(orbit.main.len(), MAX_ORBIT_LEN);
+        assert_eq!(orbit.to_bytes().len(), ORBIT_BUFFER_SIZE);
+    }
+}
@@ -0,0 +1,189 @@
+//! Camera positions in arbitrary precision.
+//!
+//! Even `Df64` runs out of digits eventually, but nothing on the CPU has
+//! to. The shader never sees more than an `f32` offset from a position,
+//! see `perturbation`.
+
+use std::{fmt, str::FromStr};
+
+use dashu_float::{
+    round::mode::{HalfAway, HalfEven},
+    DBig, FBig,
+};
+
+/// Bits of mantissa every coordinate keeps. The offsets the shader works
+/// with can't get much smaller than 2^-149 anyway
+pub(crate) const BITS: usize = 256;
+
+// Decimal digits `Display` writes, enough to parse back to the same `Real`
+const DIGITS: usize = 80;
+
+/// A binary float with `BITS` bits of mantissa.
+pub(crate) type Real = FBig<HalfEven>;
+
+/// `x` with `BITS` bits of precision, so nothing computed from it gets
+/// rounded to fewer.
+pub(crate) fn real(x: f64) -> Real {
+    Real::try_from(x)
+        .expect("coordinates are finite")
+        .with_precision(BITS)
+        .value()
+}
+
+/// A point of 4D space, precise to `BITS` bits. Before the slice gets
+/// rotated, so `w` is on top of `Params::slice_w`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Position {
+    coords: [Real; 4],
+}
+
+impl Position {
+    pub(crate) fn coords(&self) -> &[Real; 4] {
+        &self.coords
+    }
+
+    /// Moves by `offset`, without rounding the result to `f64`. An offset
+    /// that isn't finite has nowhere to go, so it's ignored.
+    pub fn translate(&mut self, offset: [f64; 4]) {
+        if !offset.iter().all(|x| x.is_finite()) {
+            log::warn!("not moving by {offset:?}");
+            return;
+        }
+        for (coord, offset) in self.coords.iter_mut().zip(offset) {
+            *coord = &*coord + real(offset);
+        }
+    }
+
+    /// `other - self`, rounded to `f64`.
+    pub fn offset_to(&self, other: &Position) -> [f64; 4] {
+        [0, 1, 2, 3].map(|i| (&other.coords[i] - &self.coords[i]).to_f64().value())
+    }
+
+    pub fn to_f64(&self) -> [f64; 4] {
+        self.coords.each_ref().map(|x| x.to_f64().value())
+    }
+
+    pub fn to_f32(&self) -> [f32; 4] {
+        self.coords.each_ref().map(|x| x.to_f32().value())
+    }
+}
+
+impl From<[f64; 4]> for Position {
+    fn from(pos: [f64; 4]) -> Self {
+        Self {
+            coords: pos.map(real),
+        }
+    }
+}
+
+impl From<[f32; 4]> for Position {
+    fn from(pos: [f32; 4]) -> Self {
+        pos.map(f64::from).into()
+    }
+}
+
+/// A point of the slice at `w` = 0.
+impl From<[f64; 3]> for Position {
+    fn from([x, y, z]: [f64; 3]) -> Self {
+        [x, y, z, 0.0].into()
+    }
+}
+
+impl From<[f32; 3]> for Position {
+    fn from(pos: [f32; 3]) -> Self {
+        pos.map(f64::from).into()
+    }
+}
+
+/// Written as `x,y,z,w` in decimal, with as many digits as it takes.
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [x, y, z, w] = self.coords.each_ref().map(|x| {
+            x.clone()
+                .with_rounding::<HalfAway>()
+                .with_base_and_precision::<10>(DIGITS)
+                .value()
+        });
+        write!(f, "{x},{y},{z},{w}")
+    }
+}
+
+/// Parses three or four comma separated decimal numbers, which can have
+/// more digits than an `f64` holds. Without a fourth `w` is 0.
+impl FromStr for Position {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let coords = s
+            .split(',')
+            .map(|term| {
+                let term = term.trim();
+                let decimal = DBig::from_str(term).map_err(|e| format!("{term:?}: {e}"))?;
+                Ok(decimal
+                    .with_base_and_precision::<2>(BITS)
+                    .value()
+                    .with_rounding())
+            })
+            .collect::<Result<Vec<Real>, String>>()?;
+
+        let coords: [Real; 4] = match <[Real; 3]>::try_from(coords) {
+            Ok([x, y, z]) => [x, y, z, real(0.0)],
+            Err(coords) => coords
+                .try_into()
+                .map_err(|v: Vec<Real>| format!("expected 3 or 4 numbers but got {}", v.len()))?,
+        };
+        Ok(Self { coords })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_what_f64_loses() {
+        let mut pos = Position::from([1.0f64, 0.0, 0.0]);
+        pos.translate([1e-30, 0.0, 0.0, 0.0]);
+        assert_eq!(pos.to_f64(), [1.0, 0.0, 0.0, 0.0]);
+
+        pos.translate([-1.0, 0.0, 0.0, 0.0]);
+        assert_eq!(pos.to_f64(), [1e-30, 0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn ignores_offsets_that_arent_finite() {
+        let mut pos = Position::from([1.0f64, 2.0, 3.0]);
+        pos.translate([f64::INFINITY, 0.0, 0.0, 0.0]);
+        pos.translate([0.0, f64::NAN, 0.0, 0.0]);
+        assert_eq!(pos.to_f64(), [1.0, 2.0, 3.0, 0.0]);
+    }
+
+    #[test]
+    fn parses_more_digits_than_f64_holds() {
+        let pos: Position = "1.00000000000000000000000001,-0.5,3".parse().unwrap();
+        let mut moved = pos.clone();
+        moved.translate([-1.0, 0.0, 0.0, 0.0]);
+        assert!((moved.to_f64()[0] - 1e-26).abs() < 1e-40);
+        assert_eq!(pos.to_f64(), [1.0, -0.5, 3.0, 0.0]);
+    }
+
+    #[test]
+    fn round_trips_through_strings() {
+        let mut pos = Position::from([-0.3f64, 0.1, -3.0, 0.7]);
+        pos.translate([1e-25, 0.0, 0.0, 1e-30]);
+        assert_eq!(pos.to_string().parse::<Position>().unwrap(), pos);
+    }
+
+    #[test]
+    fn w_defaults_to_zero() {
+        let pos: Position = "1,2,3".parse().unwrap();
+        assert_eq!(pos, Position::from([1.0f64, 2.0, 3.0, 0.0]));
+    }
+
+    #[test]
+    fn rejects_the_wrong_number_of_coordinates() {
+        assert!("1,2".parse::<Position>().is_err());
+        assert!("1,2,3,4,5".parse::<Position>().is_err());
+        assert!("1,2,x".parse::<Position>().is_err());
+    }
+}
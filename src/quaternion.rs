@@ -0,0 +1,415 @@
+use std::ops::{Add, AddAssign, Div, Mul, MulAssign, Neg, Sub, SubAssign};
+
+use cgmath::Vector4;
+
+/// A quaternion `r + i𝐢 + j𝐣 + k𝐤`.
+///
+/// In the shader quaternions are a `vec4<f32>` with the real part in `x`,
+/// which is what the `Vector4` conversions follow.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Quaternion {
+    pub r: f32,
+    pub i: f32,
+    pub j: f32,
+    pub k: f32,
+}
+
+impl Quaternion {
+    pub const ZERO: Self = Self::new(0.0, 0.0, 0.0, 0.0);
+    pub const ONE: Self = Self::new(1.0, 0.0, 0.0, 0.0);
+    pub const I: Self = Self::new(0.0, 1.0, 0.0, 0.0);
+    pub const J: Self = Self::new(0.0, 0.0, 1.0, 0.0);
+    pub const K: Self = Self::new(0.0, 0.0, 0.0, 1.0);
+
+    pub const fn new(r: f32, i: f32, j: f32, k: f32) -> Self {
+        Self { r, i, j, k }
+    }
+
+    pub fn dot(self, other: Self) -> f32 {
+        self.r * other.r + self.i * other.i + self.j * other.j + self.k * other.k
+    }
+
+    pub fn norm_squared(self) -> f32 {
+        self.dot(self)
+    }
+
+    pub fn norm(self) -> f32 {
+        self.norm_squared().sqrt()
+    }
+
+    pub fn conjugate(self) -> Self {
+        Self::new(self.r, -self.i, -self.j, -self.k)
+    }
+
+    /// The multiplicative inverse, `q * q.inverse() == 1`.
+    pub fn inverse(self) -> Self {
+        self.conjugate() / self.norm_squared()
+    }
+
+    /// Length of the imaginary part.
+    fn imaginary_norm(self) -> f32 {
+        (self.i * self.i + self.j * self.j + self.k * self.k).sqrt()
+    }
+
+    /// Scales the imaginary part by `factor` and replaces the real part.
+    fn with_imaginary_scaled(self, r: f32, factor: f32) -> Self {
+        Self::new(r, self.i * factor, self.j * factor, self.k * factor)
+    }
+
+    pub fn exp(self) -> Self {
+        let v = self.imaginary_norm();
+        let scale = self.r.exp();
+        // sin(v) / v goes to 1 as v goes to 0
+        let factor = if v > 0.0 { v.sin() / v } else { 1.0 };
+
+        self.with_imaginary_scaled(scale * v.cos(), scale * factor)
+    }
+
+    /// The principal natural logarithm, `q.ln().exp() == q`.
+    pub fn ln(self) -> Self {
+        let norm = self.norm();
+        let v = self.imaginary_norm();
+        let angle = v.atan2(self.r);
+        let factor = if v > 0.0 { angle / v } else { 0.0 };
+
+        self.with_imaginary_scaled(norm.ln(), factor)
+    }
+
+    /// Raises the quaternion to a real power in polar form,
+    /// `|q|^p (cos(pθ) + n̂ sin(pθ))`.
+    ///
+    /// Uses the principal branch, so like `ln` it is discontinuous along
+    /// the negative real axis, where `n̂` is taken to be 𝐢.
+    pub fn powf(self, p: f32) -> Self {
+        let norm = self.norm();
+        if norm == 0.0 {
+            return Self::ZERO;
+        }
+
+        let v = self.imaginary_norm();
+        let angle = v.atan2(self.r) * p;
+        let scale = norm.powf(p);
+
+        if v > 0.0 {
+            self.with_imaginary_scaled(scale * angle.cos(), scale * angle.sin() / v)
+        } else {
+            Self::new(scale * angle.cos(), scale * angle.sin(), 0.0, 0.0)
+        }
+    }
+
+    /// How much `q.powf(p)` changes when `q` moves by `dq`, to first
+    /// order. Unlike for real numbers this isn't `p q^(p-1) dq`, as `q` and
+    /// `dq` don't commute.
+    pub fn powf_derivative(self, p: f32, dq: Self) -> Self {
+        let norm = self.norm();
+        if norm == 0.0 {
+            return Self::ZERO;
+        }
+
+        let v = self.imaginary_norm();
+        let angle = v.atan2(self.r) * p;
+        let scale = norm.powf(p);
+
+        // Unit axis of the imaginary part, 𝐢 on the real axis like `powf`
+        let axis = if v > 0.0 {
+            self.with_imaginary_scaled(0.0, 1.0 / v)
+        } else {
+            Self::I
+        };
+        let dv = dq.with_imaginary_scaled(0.0, 1.0);
+
+        let dnorm = self.dot(dq) / norm;
+        let dv_along_axis = axis.dot(dv);
+        let dangle = p * (self.r * dv_along_axis - v * dq.r) / (norm * norm);
+        let dscale = p * scale * dnorm / norm;
+        // scale * sin(angle) / v goes to p * scale / norm as v goes to 0
+        let turn = if v > 0.0 {
+            scale * angle.sin() / v
+        } else {
+            p * scale / norm
+        };
+
+        let real = dscale * angle.cos() - scale * angle.sin() * dangle;
+        let along_axis = dscale * angle.sin() + scale * angle.cos() * dangle;
+        Self::new(real, 0.0, 0.0, 0.0) + axis * along_axis + (dv - axis * dv_along_axis) * turn
+    }
+
+    /// Raises the quaternion to an integer power by repeated squaring.
+    pub fn powi(self, n: i32) -> Self {
+        let mut base = if n < 0 { self.inverse() } else { self };
+        let mut exp = n.unsigned_abs();
+        let mut result = Self::ONE;
+
+        while exp > 0 {
+            if exp & 1 == 1 {
+                result *= base;
+            }
+            base *= base;
+            exp >>= 1;
+        }
+
+        result
+    }
+}
+
+impl From<Vector4<f32>> for Quaternion {
+    fn from(v: Vector4<f32>) -> Self {
+        Self::new(v.x, v.y, v.z, v.w)
+    }
+}
+
+impl From<Quaternion> for Vector4<f32> {
+    fn from(q: Quaternion) -> Self {
+        Vector4::new(q.r, q.i, q.j, q.k)
+    }
+}
+
+impl From<[f32; 4]> for Quaternion {
+    fn from([r, i, j, k]: [f32; 4]) -> Self {
+        Self::new(r, i, j, k)
+    }
+}
+
+impl From<Quaternion> for [f32; 4] {
+    fn from(q: Quaternion) -> Self {
+        [q.r, q.i, q.j, q.k]
+    }
+}
+
+impl From<f32> for Quaternion {
+    fn from(r: f32) -> Self {
+        Self::new(r, 0.0, 0.0, 0.0)
+    }
+}
+
+impl Add for Quaternion {
+    type Output = Self;
+
+    fn add(self, o: Self) -> Self {
+        Self::new(self.r + o.r, self.i + o.i, self.j + o.j, self.k + o.k)
+    }
+}
+
+impl Sub for Quaternion {
+    type Output = Self;
+
+    fn sub(self, o: Self) -> Self {
+        Self::new(self.r - o.r, self.i - o.i, self.j - o.j, self.k - o.k)
+    }
+}
+
+impl Neg for Quaternion {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::new(-self.r, -self.i, -self.j, -self.k)
+    }
+}
+
+/// The Hamilton product.
+impl Mul for Quaternion {
+    type Output = Self;
+
+    fn mul(self, o: Self) -> Self {
+        // (a + b𝐢 + c𝐣 + d𝐤)(e + f𝐢 + g𝐣 + h𝐤) =
+        // 1(ae - bf - cg - dh) +
+        // 𝐢(af + be + ch - dg) +
+        // 𝐣(ag - bh + ce + df) +
+        // 𝐤(ah + bg - cf + de)
+        Self::new(
+            self.r * o.r - self.i * o.i - self.j * o.j - self.k * o.k,
+            self.r * o.i + self.i * o.r + self.j * o.k - self.k * o.j,
+            self.r * o.j - self.i * o.k + self.j * o.r + self.k * o.i,
+            self.r * o.k + self.i * o.j - self.j * o.i + self.k * o.r,
+        )
+    }
+}
+
+impl Mul<f32> for Quaternion {
+    type Output = Self;
+
+    fn mul(self, s: f32) -> Self {
+        Self::new(self.r * s, self.i * s, self.j * s, self.k * s)
+    }
+}
+
+impl Mul<Quaternion> for f32 {
+    type Output = Quaternion;
+
+    fn mul(self, q: Quaternion) -> Quaternion {
+        q * self
+    }
+}
+
+impl Div<f32> for Quaternion {
+    type Output = Self;
+
+    fn div(self, s: f32) -> Self {
+        self * (1.0 / s)
+    }
+}
+
+impl AddAssign for Quaternion {
+    fn add_assign(&mut self, o: Self) {
+        *self = *self + o;
+    }
+}
+
+impl SubAssign for Quaternion {
+    fn sub_assign(&mut self, o: Self) {
+        *self = *self - o;
+    }
+}
+
+impl MulAssign for Quaternion {
+    fn mul_assign(&mut self, o: Self) {
+        *self = *self * o;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn quaternion() -> impl Strategy<Value = Quaternion> {
+        (
+            -10.0f32..10.0,
+            -10.0f32..10.0,
+            -10.0f32..10.0,
+            -10.0f32..10.0,
+        )
+            .prop_map(|(r, i, j, k)| Quaternion::new(r, i, j, k))
+    }
+
+    fn nonzero_quaternion() -> impl Strategy<Value = Quaternion> {
+        quaternion().prop_filter("too close to zero", |q| q.norm() > 0.1)
+    }
+
+    /// Whether `a` and `b` are equal relative to `scale`.
+    fn close(a: Quaternion, b: Quaternion, scale: f32) -> bool {
+        (a - b).norm() <= 1e-5 * scale.max(1.0)
+    }
+
+    #[test]
+    fn hamilton_identities() {
+        let (i, j, k) = (Quaternion::I, Quaternion::J, Quaternion::K);
+        let minus_one = -Quaternion::ONE;
+
+        assert_eq!(i * i, minus_one);
+        assert_eq!(j * j, minus_one);
+        assert_eq!(k * k, minus_one);
+        assert_eq!(i * j * k, minus_one);
+
+        assert_eq!(i * j, k);
+        assert_eq!(j * k, i);
+        assert_eq!(k * i, j);
+        assert_eq!(j * i, -k);
+        assert_eq!(k * j, -i);
+        assert_eq!(i * k, -j);
+    }
+
+    #[test]
+    fn exp_of_zero_is_one() {
+        assert_eq!(Quaternion::ZERO.exp(), Quaternion::ONE);
+        assert_eq!(Quaternion::ONE.ln(), Quaternion::ZERO);
+    }
+
+    proptest! {
+        #[test]
+        fn multiplication_is_associative(a in quaternion(), b in quaternion(), c in quaternion()) {
+            let scale = a.norm() * b.norm() * c.norm();
+            prop_assert!(close((a * b) * c, a * (b * c), scale));
+        }
+
+        #[test]
+        fn norm_is_multiplicative(a in quaternion(), b in quaternion()) {
+            let product = a.norm() * b.norm();
+            prop_assert!(((a * b).norm() - product).abs() <= 1e-5 * product.max(1.0));
+        }
+
+        #[test]
+        fn conjugate_reverses_products(a in quaternion(), b in quaternion()) {
+            let scale = a.norm() * b.norm();
+            prop_assert!(close((a * b).conjugate(), b.conjugate() * a.conjugate(), scale));
+        }
+
+        #[test]
+        fn inverse_is_inverse(q in nonzero_quaternion()) {
+            prop_assert!(close(q * q.inverse(), Quaternion::ONE, 1.0));
+            prop_assert!(close(q.inverse() * q, Quaternion::ONE, 1.0));
+        }
+
+        #[test]
+        fn ln_inverts_exp(q in nonzero_quaternion()) {
+            prop_assert!(close(q.ln().exp(), q, q.norm()));
+        }
+
+        #[test]
+        fn exp_of_real_sum_is_product(a in -3.0f32..3.0, q in quaternion()) {
+            // exp(a + q) = exp(a) exp(q) holds because reals commute with everything
+            let lhs = (Quaternion::from(a) + q).exp();
+            let rhs = Quaternion::from(a).exp() * q.exp();
+            prop_assert!(close(lhs, rhs, lhs.norm()));
+        }
+
+        #[test]
+        fn powi_is_repeated_multiplication(q in quaternion(), n in 0i32..8) {
+            let mut expected = Quaternion::ONE;
+            for _ in 0..n {
+                expected *= q;
+            }
+            prop_assert!(close(q.powi(n), expected, expected.norm()));
+        }
+
+        #[test]
+        fn powf_matches_powi(q in nonzero_quaternion(), n in -3i32..6) {
+            let expected = q.powi(n);
+            prop_assert!(close(q.powf(n as f32), expected, expected.norm() * 10.0));
+        }
+
+        #[test]
+        fn powf_norm_is_norm_to_the_power(q in nonzero_quaternion(), p in -3.0f32..6.0) {
+            let expected = q.norm().powf(p);
+            prop_assert!((q.powf(p).norm() - expected).abs() <= 1e-4 * expected.max(1.0));
+        }
+
+        #[test]
+        fn powf_adds_exponents(q in nonzero_quaternion(), a in -2.0f32..3.0, b in -2.0f32..3.0) {
+            // Powers of the same quaternion commute, as long as the angle
+            // stays on the principal branch
+            prop_assume!(((a + b) * q.ln().imaginary_norm()).abs() < std::f32::consts::PI);
+            let expected = q.powf(a + b);
+            prop_assert!(close(q.powf(a) * q.powf(b), expected, expected.norm() * 10.0));
+        }
+
+        #[test]
+        fn powf_derivative_of_square_is_product_rule(q in quaternion(), dq in quaternion()) {
+            let expected = q * dq + dq * q;
+            prop_assert!(close(q.powf_derivative(2.0, dq), expected, expected.norm() * 10.0));
+        }
+
+        #[test]
+        fn powf_derivative_matches_finite_differences(
+            q in nonzero_quaternion(),
+            dq in quaternion(),
+            p in 1.0f32..6.0,
+        ) {
+            // Stay away from the branch cut along the negative real axis
+            prop_assume!(q.r > 0.0 || q.imaginary_norm() > 0.1 * q.norm());
+            let h = 1e-3 * q.norm() / dq.norm().max(1.0);
+            let expected = (q + dq * h).powf(p) - (q - dq * h).powf(p);
+            let actual = q.powf_derivative(p, dq) * (2.0 * h);
+            prop_assert!(
+                (actual - expected).norm() <= 1e-2 * expected.norm().max(actual.norm()) + 1e-6 * q.norm().powf(p),
+                "{actual:?} != {expected:?}"
+            );
+        }
+
+        #[test]
+        fn negative_powi_is_inverse(q in nonzero_quaternion(), n in 1i32..5) {
+            let product = q.powi(n) * q.powi(-n);
+            prop_assert!(close(product, Quaternion::ONE, 1.0));
+        }
+    }
+}
@@ -0,0 +1,231 @@
+//! Rotations of 4D space, built from rotations in the six coordinate
+//! planes.
+
+use std::ops::Mul;
+
+use cgmath::{
+    InnerSpace, Matrix4, One, Quaternion, Rad, Rotation3, SquareMatrix, Vector3, Vector4,
+};
+
+/// The coordinate planes, as pairs of axes with 0 = x ... 3 = w, in the
+/// order `from_angles` applies them.
+pub const PLANES: [(usize, usize); 6] = [(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)];
+
+/// Rotation by `angle` radians in the plane spanned by axes `a` and `b`,
+/// turning `a` towards `b`.
+pub fn plane_rotation(a: usize, b: usize, angle: f32) -> Matrix4<f32> {
+    let (sin, cos) = angle.sin_cos();
+    let mut m = Matrix4::identity();
+
+    // Column major, so m[column][row]
+    m[a][a] = cos;
+    m[a][b] = sin;
+    m[b][a] = -sin;
+    m[b][b] = cos;
+    m
+}
+
+/// Composes rotations in the xy, xz, xw, yz, yw and zw planes by the
+/// respective angles in radians.
+pub fn from_angles(angles: [f32; 6]) -> Matrix4<f32> {
+    PLANES
+        .iter()
+        .zip(angles)
+        .fold(Matrix4::identity(), |m, (&(a, b), angle)| {
+            plane_rotation(a, b, angle) * m
+        })
+}
+
+/// A rotation of 4D space as a pair of unit quaternions, `v` going to
+/// `left * v * right` with `(x, y, z, w)` read as `w + xi + yj + zk`.
+///
+/// Unlike a matrix it can't drift away from being a rotation beyond what
+/// normalizing fixes, and the rotations that leave `w` alone are the usual
+/// 3D ones, `q * v * q⁻¹`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Rotation4 {
+    pub left: Quaternion<f32>,
+    pub right: Quaternion<f32>,
+}
+
+impl Rotation4 {
+    pub fn identity() -> Self {
+        Self {
+            left: Quaternion::one(),
+            right: Quaternion::one(),
+        }
+    }
+
+    /// Rotation by `angle` radians in the plane spanned by axis `axis`
+    /// (0 = x, 1 = y, 2 = z) and w, turning `axis` towards w.
+    pub fn towards_w(axis: usize, angle: f32) -> Self {
+        let mut unit = Vector3::new(0.0, 0.0, 0.0);
+        unit[axis] = 1.0;
+        // Multiplying by e^(-uθ/2) on both sides turns u towards 1 by θ,
+        // and leaves the other plane alone
+        let half = Quaternion::from_axis_angle(unit, Rad(-angle));
+        Self {
+            left: half,
+            right: half,
+        }
+    }
+
+    /// Scales both quaternions back to unit length, so rounding errors
+    /// don't pile up.
+    pub fn normalize(self) -> Self {
+        Self {
+            left: self.left.normalize(),
+            right: self.right.normalize(),
+        }
+    }
+
+    /// The rotation a fraction `t` of the way from `self` to `other`,
+    /// turning at a constant rate.
+    pub fn slerp(self, other: Self, t: f32) -> Self {
+        // The negated pair is the same rotation, and whichever of the two is
+        // closer has the shorter way there
+        let other = if self.left.dot(other.left) + self.right.dot(other.right) < 0.0 {
+            Self {
+                left: -other.left,
+                right: -other.right,
+            }
+        } else {
+            other
+        };
+        Self {
+            left: slerp(self.left, other.left, t),
+            right: slerp(self.right, other.right, t),
+        }
+    }
+
+    pub fn rotate(&self, v: Vector4<f32>) -> Vector4<f32> {
+        let q = self.left * Quaternion::new(v.w, v.x, v.y, v.z) * self.right;
+        Vector4::new(q.v.x, q.v.y, q.v.z, q.s)
+    }
+}
+
+/// `Quaternion::slerp`, except that it never swaps `b` for `-b`. Only both
+/// halves of a `Rotation4` together can be swapped for their negatives.
+fn slerp(a: Quaternion<f32>, b: Quaternion<f32>, t: f32) -> Quaternion<f32> {
+    let angle = a.dot(b).clamp(-1.0, 1.0).acos();
+    let sin = angle.sin();
+    if sin < 1e-4 {
+        // Close enough to a straight line, and even to each other if they
+        // are opposite
+        return (a * (1.0 - t) + b * t).normalize();
+    }
+    (a * (((1.0 - t) * angle).sin() / sin) + b * ((t * angle).sin() / sin)).normalize()
+}
+
+/// The 3D rotation `q`, which leaves `w` alone.
+impl From<Quaternion<f32>> for Rotation4 {
+    fn from(q: Quaternion<f32>) -> Self {
+        let q = q.normalize();
+        Self {
+            left: q,
+            right: q.conjugate(),
+        }
+    }
+}
+
+/// `self` after `other`, like multiplying matrices.
+impl Mul for Rotation4 {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        Self {
+            left: self.left * other.left,
+            right: other.right * self.right,
+        }
+    }
+}
+
+impl From<Rotation4> for Matrix4<f32> {
+    fn from(rotation: Rotation4) -> Self {
+        Matrix4::from_cols(
+            rotation.rotate(Vector4::unit_x()),
+            rotation.rotate(Vector4::unit_y()),
+            rotation.rotate(Vector4::unit_z()),
+            rotation.rotate(Vector4::unit_w()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::Matrix;
+
+    fn assert_matrices_close(a: Matrix4<f32>, b: Matrix4<f32>) {
+        for i in 0..4 {
+            assert!((a[i] - b[i]).magnitude() < 1e-5, "{a:?} != {b:?}");
+        }
+    }
+
+    #[test]
+    fn plane_rotation_turns_first_axis_towards_second() {
+        let m = plane_rotation(0, 3, std::f32::consts::FRAC_PI_2);
+        let turned = m * Vector4::unit_x();
+
+        assert!((turned - Vector4::unit_w()).magnitude() < 1e-6);
+    }
+
+    #[test]
+    fn from_angles_is_orthonormal() {
+        let m = from_angles([0.1, -0.7, 1.3, 2.0, -2.9, 0.4]);
+        let identity = m * m.transpose();
+
+        for i in 0..4 {
+            for j in 0..4 {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!((identity[i][j] - expected).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn towards_w_matches_plane_rotation() {
+        for axis in 0..3 {
+            assert_matrices_close(
+                Rotation4::towards_w(axis, 0.8).into(),
+                plane_rotation(axis, 3, 0.8),
+            );
+        }
+    }
+
+    #[test]
+    fn rotation4_from_quaternion_leaves_w_alone() {
+        let q = Quaternion::from_angle_y(Rad(0.4)) * Quaternion::from_angle_x(Rad(-1.1));
+        assert_matrices_close(Rotation4::from(q).into(), q.into());
+    }
+
+    #[test]
+    fn rotation4_slerp_goes_halfway() {
+        let from = Rotation4::from(Quaternion::from_angle_y(Rad(0.4)));
+        let to = from * Rotation4::towards_w(0, 1.0) * Rotation4::towards_w(2, -2.0);
+
+        assert_matrices_close(from.slerp(to, 0.0).into(), from.into());
+        assert_matrices_close(from.slerp(to, 1.0).into(), to.into());
+
+        let halfway = from.slerp(to, 0.5);
+        let rest = Matrix4::from(halfway).transpose() * Matrix4::from(to);
+        let first = Matrix4::from(from).transpose() * Matrix4::from(halfway);
+        assert_matrices_close(first, rest);
+
+        // Either sign of the pair is the same rotation
+        let negated = Rotation4 {
+            left: -to.left,
+            right: -to.right,
+        };
+        assert_matrices_close(from.slerp(negated, 0.5).into(), halfway.into());
+    }
+
+    #[test]
+    fn rotation4_multiplies_like_matrices() {
+        let a = Rotation4::from(Quaternion::from_angle_z(Rad(0.3))) * Rotation4::towards_w(1, 1.2);
+        let b = Rotation4::towards_w(0, -0.5) * Rotation4::towards_w(2, 2.0);
+
+        assert_matrices_close((a * b).into(), Matrix4::from(a) * Matrix4::from(b));
+        assert_matrices_close(Rotation4::identity().into(), Matrix4::identity());
+    }
+}
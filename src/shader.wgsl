@@ -1,11 +1,13 @@
 struct VertexInput {
     @location(0) position: vec3<f32>,
-    @location(1) real_pos: vec3<f32>,
+    @location(1) screen_pos: vec2<f32>,
 };
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
-    @location(0) real_pos: vec3<f32>,
+    @location(0) real_pos: vec4<f32>,
+    // real_pos before the camera rotation
+    @location(1) local_pos: vec3<f32>,
 };
 
 struct ZoomUniform {
@@ -15,27 +17,87 @@ struct ZoomUniform {
 @group(0) @binding(0)
 var<uniform> zoom: ZoomUniform;
 
+// Must be the same as camera.rs
 struct CameraUniform {
-    pos: vec3<f32>,
+    // In 4D, before moving along w to the slice
+    pos: vec4<f32>,
+    // Columns are the camera's right, up, forward and the w axis it doesn't
+    // see, rays stay in the hyperplane of the first three
     rot: mat4x4<f32>,
+    // Horizontal, in radians
+    fov: f32,
+    // Width / height of the image being rendered
+    aspect: f32,
+    // What rounding pos to f32 left out
+    pos_lo: vec4<f32>,
 }
 
 @group(0) @binding(1)
 var<uniform> cam: CameraUniform;
 
-const MAX_ITERATIONS = 200;
+// Must be the same as params.rs
+struct ParamsUniform {
+    max_iterations: u32,
+    bailout: f32,
+    max_steps: u32,
+    min_distance: f32,
+    max_distance: f32,
+    delta: f32,
+    light_intensity: f32,
+    shininess: f32,
+    light_pos: vec3<f32>,
+    ambient_light: vec3<f32>,
+    k_a: vec3<f32>,
+    k_d: vec3<f32>,
+    k_s: vec3<f32>,
+    shading: u32,
+    // Rotates the slicing hyperplane through 4D space
+    slice_rotation: mat4x4<f32>,
+    // Where the slice sits along the fourth axis, before rotating
+    slice_w: f32,
+    // Render the Julia set for `julia_c` instead of the mandelbrot set
+    julia: u32,
+    julia_c: vec4<f32>,
+    // Maps applied to z between iterations, used in turn
+    flips: array<mat4x4<f32>, 4>,
+    flip_count: u32,
+    // Every how many iterations to flip, 0 meaning only before the first
+    flip_every: u32,
+    // The n in z^n + c
+    power: f32,
+    // What the distance estimate tracks as the derivative
+    derivative: u32,
+    // Which distance estimator formula to use
+    estimator: u32,
+    // Every distance estimate gets multiplied by this
+    fudge: f32,
+    // How precisely to march and iterate, see PRECISION_SINGLE
+    float_precision: u32,
+    // Always 1.0, see Df4
+    one: f32,
+}
+
+@group(0) @binding(2)
+var<uniform> params: ParamsUniform;
+
+// Must be the same as perturbation.rs
+struct ReferenceOrbit {
+    main_len: u32,
+    rebase_len: u32,
+    // Points only rebase before iterations that are a multiple of this
+    rebase_period: u32,
+    // z before every flip, first of the camera position and then of 0
+    points: array<vec4<f32>>,
+}
+
+// Only filled in with PRECISION_PERTURBATION
+@group(0) @binding(3)
+var<storage, read> orbit: ReferenceOrbit;
+
 /* const FOCUS = vec2<f32>(-0.5577, -0.6099); */
 
-const MAX_STEPS = 500;
-const FOV = 90;
 const SIZE = 10.0;
 const OBJ_POS = vec3<f32>(0.0, 0.0, 0.0);
-const LIGHT_POS = vec3<f32>(-50.0, 50.0, 50.0);
-const MIN_DISTANCE = 0.00001;
-const MAX_DISTANCE = 100.0;
-const DELTA = 0.001;
-const AMBIENT_LIGHT = vec3<f32>(0.1, 0.1, 0.1);
-const LIGHT_INTENSITY = 0.4;
 
 @vertex
 fn vs_main(
@@ -43,7 +105,15 @@ fn vs_main(
 ) -> VertexOutput {
     var out: VertexOutput;
     out.clip_position = vec4<f32>(model.position, 1.0);
-    out.real_pos = (cam.rot * vec4<f32>(model.real_pos, 1.0)).xyz;
+
+    let half_width = tan(cam.fov / 2.0);
+    let real_pos = 2.0 * SCALE * vec3(
+        model.screen_pos.x * half_width,
+        model.screen_pos.y * half_width / cam.aspect,
+        1.0,
+    );
+    out.real_pos = cam.rot * vec4<f32>(real_pos, 0.0);
+    out.local_pos = real_pos;
     return out;
 }
 
@@ -53,12 +123,12 @@ fn vs_main(
 // Fragment shader
 
 fn quaternion_mul(a: vec4<f32>, b: vec4<f32>) -> vec4<f32> {
-    // (a + bi + cj + dk)(e + fi + gk + hi) = 
-    // 1(ae - bf - cg - dh) + 
-    // i(be + af + ch - dg) + 
-    // j(ag - bh + ce + df) + 
+    // (a + bi + cj + dk)(e + fi + gj + hk) =
+    // 1(ae - bf - cg - dh) +
+    // i(af + be + ch - dg) +
+    // j(ag - bh + ce + df) +
     // k(ah + bg - cf + de)
-    // CHECK AGAIN!!!
+    // Checked against `Quaternion` in quaternion.rs by tests/shader_quaternion.rs
     return vec4<f32>(
         a.x * b.x - a.y * b.y - a.z * b.z - a.w * b.w,
         a.y * b.x + a.x * b.y + a.z * b.w - a.w * b.z,
@@ -67,38 +137,266 @@ fn quaternion_mul(a: vec4<f32>, b: vec4<f32>) -> vec4<f32> {
     );
 }
 
-fn mandelbrot(pos: vec4<f32>) -> f32 {
-    var iters = 0;
-    let c = pos;
-    var z = vec4(pos.x, -pos.z, -pos.y, pos.w);
-    var dz = vec4(1.0, vec3(0.0));
+// Raises q to a real power in polar form, |q|^p (cos(pθ) + n sin(pθ))
+// Must be the same as `Quaternion::powf` in quaternion.rs
+fn quaternion_pow(q: vec4<f32>, p: f32) -> vec4<f32> {
+    let norm = length(q);
+    if norm == 0.0 {
+        return vec4(0.0);
+    }
+
+    let v = length(q.yzw);
+    let angle = atan2(v, q.x) * p;
+    let scale = pow(norm, p);
+
+    var axis = vec3(1.0, 0.0, 0.0);
+    if v > 0.0 {
+        axis = q.yzw / v;
+    }
+    return scale * vec4(cos(angle), axis * sin(angle));
+}
+
+// How much quaternion_pow(q, p) changes when q moves by dq, to first order
+// Must be the same as `Quaternion::powf_derivative` in quaternion.rs
+fn quaternion_pow_derivative(q: vec4<f32>, p: f32, dq: vec4<f32>) -> vec4<f32> {
+    let norm = length(q);
+    if norm == 0.0 {
+        return vec4(0.0);
+    }
+
+    let v = length(q.yzw);
+    let angle = atan2(v, q.x) * p;
+    let scale = pow(norm, p);
+
+    var axis = vec3(1.0, 0.0, 0.0);
+    // scale * sin(angle) / v goes to p * scale / norm as v goes to 0
+    var turn = p * scale / norm;
+    if v > 0.0 {
+        axis = q.yzw / v;
+        turn = scale * sin(angle) / v;
+    }
 
-    while iters <= MAX_ITERATIONS {
-        z = quaternion_mul(z, z) + c;
-        dz = 2.0 * vec4(z.x * dz.x - dot(z.yzw, dz.yzw), z.x * dz.yzw + dz.x * z.yzw + cross(z.yzw, dz.yzw));
+    let dnorm = dot(q, dq) / norm;
+    let dv_along_axis = dot(axis, dq.yzw);
+    let dangle = p * (q.x * dv_along_axis - v * dq.x) / (norm * norm);
+    let dscale = p * scale * dnorm / norm;
+
+    let real = dscale * cos(angle) - scale * sin(angle) * dangle;
+    let along_axis = dscale * sin(angle) + scale * cos(angle) * dangle;
+    return vec4(real, axis * along_axis + (dq.yzw - axis * dv_along_axis) * turn);
+}
+
+// Must be the same as `pow_derivative` in cpu.rs
+fn pow_derivative(z: vec4<f32>, dz: vec4<f32>) -> vec4<f32> {
+    if params.power == 2.0 {
+        // Faster and more precise than going through polar form
+        return quaternion_mul(z, dz) + quaternion_mul(dz, z);
+    } else {
+        return quaternion_pow_derivative(z, params.power, dz);
+    }
+}
+
+// Must be the same as `Derivative` in params.rs
+const DERIVATIVE_QUATERNION = 0u;
+const DERIVATIVE_JACOBIAN = 1u;
+
+// Whether z gets flipped before iteration `iteration`
+fn flip_applies(iteration: u32) -> bool {
+    if params.flip_count == 0u {
+        return false;
+    } else if params.flip_every == 0u {
+        return iteration == 0u;
+    } else {
+        return iteration % params.flip_every == 0u;
+    }
+}
+
+struct Escape {
+    // Estimated distance to the set
+    distance: f32,
+    // How many iterations it took to escape
+    iterations: u32,
+    // ln(r) of the escaped z. The escape potential is this over
+    // n^iterations, see gradient_estimate
+    log_radius: f32,
+}
+
+// Must be the same as `Estimator` in params.rs
+const ESTIMATOR_LOG = 0u;
+const ESTIMATOR_LINEAR = 1u;
+const ESTIMATOR_GRADIENT = 2u;
+
+// What the distance estimate tracks while iterating, see `Derivative` in
+// params.rs. Only one of them gets updated
+struct Derivatives {
+    dz: vec4<f32>,
+    jacobian: mat4x4<f32>,
+}
+
+fn flip_derivatives(derivatives: Derivatives, flip: mat4x4<f32>) -> Derivatives {
+    // The quaternion derivative ignores the flip
+    return Derivatives(derivatives.dz, flip * derivatives.jacobian);
+}
+
+// Takes the derivatives of z to those of z^n + c, where dc is the derivative
+// of c with respect to pos
+fn step_derivatives(derivatives: Derivatives, z: vec4<f32>, dc: f32) -> Derivatives {
+    var result = derivatives;
+    if params.derivative == DERIVATIVE_JACOBIAN {
+        let jacobian = derivatives.jacobian;
+        result.jacobian = mat4x4<f32>(
+            pow_derivative(z, jacobian[0]) + vec4(dc, 0.0, 0.0, 0.0),
+            pow_derivative(z, jacobian[1]) + vec4(0.0, dc, 0.0, 0.0),
+            pow_derivative(z, jacobian[2]) + vec4(0.0, 0.0, dc, 0.0),
+            pow_derivative(z, jacobian[3]) + vec4(0.0, 0.0, 0.0, dc),
+        );
+    } else if params.power == 2.0 {
+        // d(z^n + c)/dpos = n z^(n-1) dz + dc/dpos. The derivative isn't
+        // really a quaternion product, but the norms match, which is all
+        // the distance estimate needs
+        result.dz = 2.0 * quaternion_mul(z, derivatives.dz) + vec4(dc, vec3(0.0));
+    } else {
+        result.dz = params.power * quaternion_mul(quaternion_pow(z, params.power - 1.0), derivatives.dz) + vec4(dc, vec3(0.0));
+    }
+    return result;
+}
+
+// The Escape for a z that escaped after iters iterations
+fn escape(z: vec4<f32>, derivatives: Derivatives, iters: u32) -> Escape {
+    // The Frobenius norm is never smaller than how much the Jacobian
+    // stretches any direction, so the estimate errs on the short side
+    var derivative = length(derivatives.dz);
+    if params.derivative == DERIVATIVE_JACOBIAN {
+        let jacobian = derivatives.jacobian;
+        derivative = sqrt(
+            dot(jacobian[0], jacobian[0]) + dot(jacobian[1], jacobian[1]) +
+            dot(jacobian[2], jacobian[2]) + dot(jacobian[3], jacobian[3])
+        );
+    }
+
+    let r = length(z);
+    // The gradient estimator doesn't use this, see DE
+    var distance = 0.5 * log(r) * r / derivative;
+    if params.estimator == ESTIMATOR_LINEAR {
+        distance = 0.5 * r / derivative;
+    }
+    return Escape(distance, iters, log(r));
+}
+
+fn start_derivatives() -> Derivatives {
+    return Derivatives(
+        vec4(1.0, vec3(0.0)),
+        mat4x4<f32>(
+            vec4(1.0, 0.0, 0.0, 0.0),
+            vec4(0.0, 1.0, 0.0, 0.0),
+            vec4(0.0, 0.0, 1.0, 0.0),
+            vec4(0.0, 0.0, 0.0, 1.0),
+        ),
+    );
+}
+
+fn mandelbrot(pos: vec4<f32>) -> Escape {
+    var iters = 0u;
+    var c = pos;
+    // Derivative of c with respect to pos
+    var dc = 1.0;
+    if params.julia != 0u {
+        c = params.julia_c;
+        dc = 0.0;
+    }
+    var z = pos;
+    var derivatives = start_derivatives();
+    var flips_done = 0u;
+
+    while iters <= params.max_iterations {
+        if flip_applies(iters) {
+            let flip = params.flips[flips_done % params.flip_count];
+            z = flip * z;
+            derivatives = flip_derivatives(derivatives, flip);
+            flips_done++;
+        }
+
+        derivatives = step_derivatives(derivatives, z, dc);
+
+        if params.power == 2.0 {
+            // Faster and more precise than going through polar form
+            z = quaternion_mul(z, z) + c;
+        } else {
+            z = quaternion_pow(z, params.power) + c;
+        }
 
         let z2 = dot(z, z);
-        if z2 > 10.0 {
+        if z2 > params.bailout {
             break
         }
 
         iters++;
     }
 
-    let r = length(z);
-    return 0.5 * log(r) * r / length(dz);
+    return escape(z, derivatives, iters);
 }
 
-fn DE(pos: vec3<f32>) -> f32 {
-    return mandelbrot(to_quat(pos));
+// A step of params.delta along the camera's right, up or forward. Rays
+// never leave the hyperplane those span, so gradients only need those three
+fn gradient_step(axis: u32) -> vec4<f32> {
+    return cam.rot[axis] * params.delta;
 }
 
-fn normals(pos: vec3<f32>) -> vec3<f32> {
-    return normalize(vec3(
-        DE(vec3(pos.x + DELTA, pos.y, pos.z)) - DE(vec3(pos.x - DELTA, pos.y, pos.z)),
-        DE(vec3(pos.x, pos.y + DELTA, pos.z)) - DE(vec3(pos.x, pos.y - DELTA, pos.z)),
-        DE(vec3(pos.x, pos.y, pos.z + DELTA)) - DE(vec3(pos.x, pos.y, pos.z - DELTA)),
-    ));
+// escape.log_radius scaled down by n^(escape.iterations - scale)
+fn scaled_potential(escape: Escape, scale: u32) -> f32 {
+    return escape.log_radius / pow(params.power, f32(escape.iterations - scale));
+}
+
+// 0.5 G / |grad G| from the escape potential G = ln(r) / n^iterations at
+// center and the Escapes a gradient_step forward and back around it.
+// n^iterations overflows past 127 iterations at power 2, so every potential
+// is instead scaled up by n^k for the fewest iterations k of any of them,
+// which cancels out
+fn gradient_estimate(center: Escape, forward: array<Escape, 3>, back: array<Escape, 3>) -> f32 {
+    let scale = min(
+        center.iterations,
+        min(
+            min(forward[0].iterations, min(forward[1].iterations, forward[2].iterations)),
+            min(back[0].iterations, min(back[1].iterations, back[2].iterations)),
+        ),
+    );
+    let gradient = vec3(
+        scaled_potential(forward[0], scale) - scaled_potential(back[0], scale),
+        scaled_potential(forward[1], scale) - scaled_potential(back[1], scale),
+        scaled_potential(forward[2], scale) - scaled_potential(back[2], scale),
+    ) / (2.0 * params.delta);
+    return 0.5 * scaled_potential(center, scale) / length(gradient);
+}
+
+fn DE(pos: vec4<f32>) -> f32 {
+    let escape = mandelbrot(to_quat(pos));
+    var distance = escape.distance;
+    if params.estimator == ESTIMATOR_GRADIENT {
+        distance = gradient_estimate(
+            escape,
+            array<Escape, 3>(
+                mandelbrot(to_quat(pos + gradient_step(0u))),
+                mandelbrot(to_quat(pos + gradient_step(1u))),
+                mandelbrot(to_quat(pos + gradient_step(2u))),
+            ),
+            array<Escape, 3>(
+                mandelbrot(to_quat(pos - gradient_step(0u))),
+                mandelbrot(to_quat(pos - gradient_step(1u))),
+                mandelbrot(to_quat(pos - gradient_step(2u))),
+            ),
+        );
+    }
+
+    return params.fudge * distance;
+}
+
+fn normals(pos: vec4<f32>) -> vec4<f32> {
+    let gradient = vec3(
+        DE(pos + gradient_step(0u)) - DE(pos - gradient_step(0u)),
+        DE(pos + gradient_step(1u)) - DE(pos - gradient_step(1u)),
+        DE(pos + gradient_step(2u)) - DE(pos - gradient_step(2u)),
+    );
+    return normalize(cam.rot * vec4(gradient, 0.0));
 }
 
 /**
@@ -110,20 +408,15 @@ fn normals(pos: vec3<f32>) -> vec3<f32> {
  * k_s: Specular color
  * alpha: Shininess coefficient
  * p: position of point being lit
+ * N: surface normal at p
  * eye: the position of the camera
  *
  * See https://en.wikipedia.org/wiki/Phong_reflection_model#Description
  */
-const K_A = vec3<f32>(0.2, 0.2, 0.2);
-const K_D = vec3<f32>(0.7, 0.2, 0.2);
-const K_S = vec3<f32>(1.0, 1.0, 1.0);
-const SHININESS = 10.0;
-
-fn phong(p: vec3<f32>) -> vec3<f32> {
-    var color = AMBIENT_LIGHT * K_A;
+fn phong(p: vec4<f32>, N: vec4<f32>) -> vec3<f32> {
+    var color = params.ambient_light * params.k_a;
 
-    let N = normals(p);
-    let L = normalize(LIGHT_POS - p);
+    let L = normalize(vec4(params.light_pos, 0.0) - p);
     let V = normalize(cam.pos - p);
     let R = normalize(reflect(-L, N));
 
@@ -131,41 +424,435 @@ fn phong(p: vec3<f32>) -> vec3<f32> {
     let dot_RV = dot(R, V);
 
     if dot_LN >= 0.0 && dot_RV < 0.0 {
-        color += LIGHT_INTENSITY * K_D * dot_LN;
+        color += params.light_intensity * params.k_d * dot_LN;
     } else if dot_LN >= 0.0 {
-        color += LIGHT_INTENSITY * (K_D * dot_LN + K_S * pow(dot_RV, SHININESS));
+        color += params.light_intensity * (params.k_d * dot_LN + params.k_s * pow(dot_RV, params.shininess));
     }
 
     return color;
 }
 
+// Must be the same as `Shading` in params.rs
+const SHADING_STEPS = 0u;
+const SHADING_NORMALS = 1u;
+const SHADING_PHONG = 2u;
+const SHADING_DEPTH = 3u;
+const SHADING_ITERATIONS = 4u;
+
 // Input a pos, outputs a color
-fn on_hit(pos: vec3<f32>) -> vec3<f32> {
-    // return normals(pos);
-    return phong(pos);
+fn on_hit(pos: vec4<f32>) -> vec3<f32> {
+    if params.shading == SHADING_NORMALS {
+        return normals(pos).xyz * 0.5 + 0.5;
+    } else if params.shading == SHADING_PHONG {
+        return phong(pos, normals(pos));
+    } else if params.shading == SHADING_DEPTH {
+        return vec3(1.0 / (1.0 + length(pos - cam.pos)));
+    } else {
+        let iterations = mandelbrot(to_quat(pos)).iterations;
+        return vec3(f32(iterations) / f32(params.max_iterations));
+    }
 }
 
-fn to_quat(pos: vec3<f32>) -> vec4<f32> {
-    return vec4(pos, 0.0);
+// Maps a point into quaternion space, by moving it along w to the slice and
+// rotating that
+fn to_quat(pos: vec4<f32>) -> vec4<f32> {
+    return params.slice_rotation * (pos + vec4(0.0, 0.0, 0.0, params.slice_w));
 }
 
-fn get_color(real_pos: vec3<f32>) -> vec3<f32> {
+fn get_color(real_pos: vec4<f32>) -> vec3<f32> {
     let ray_direction = normalize(real_pos);
     var ray_pos = real_pos + cam.pos;
 
     var distance = DE(ray_pos);
-    var steps = 0;
+    var steps = 0u;
 
-    while steps <= MAX_STEPS && distance > MIN_DISTANCE && distance < MAX_DISTANCE {
+    while steps <= params.max_steps && distance > params.min_distance && distance < params.max_distance {
         ray_pos += ray_direction * distance;
         distance = DE(ray_pos);
         steps++;
     }
 
-    return vec3(f32(steps) / f32(MAX_STEPS), 0.0, 0.0);
+    if params.shading == SHADING_STEPS {
+        return vec3(f32(steps) / f32(params.max_steps), 0.0, 0.0);
+    } else if distance <= params.min_distance {
+        return on_hit(ray_pos);
+    } else {
+        return vec3(0.0);
+    }
+}
+
+// Double-float arithmetic: each number is the unevaluated sum hi + lo of two
+// f32s, for about twice the precision. Every operation is rounded exactly as
+// written, so don't "simplify" any of them
+// Shader compilers do simplify them though, for example (a + b) - a to b,
+// which throws the rounding errors away. Multiplying by params.one, which
+// they can't tell is 1, stops that
+// Must be the same as df64.rs
+struct Df4 {
+    hi: vec4<f32>,
+    lo: vec4<f32>,
+}
+
+fn df(hi: vec4<f32>) -> Df4 {
+    return Df4(hi, vec4(0.0));
+}
+
+// Component i of a, in all four components
+fn df_splat(a: Df4, i: u32) -> Df4 {
+    return Df4(vec4(a.hi[i]), vec4(a.lo[i]));
+}
+
+// a + b and its rounding error
+fn two_sum(a: vec4<f32>, b: vec4<f32>) -> Df4 {
+    let s = (a + b) * params.one;
+    let v = s - a;
+    let e = (a - (s - v)) + (b - v);
+    return Df4(s, e);
+}
+
+// two_sum for when |a| >= |b|
+fn quick_two_sum(a: vec4<f32>, b: vec4<f32>) -> Df4 {
+    let s = (a + b) * params.one;
+    let e = b - (s - a);
+    return Df4(s, e);
+}
+
+// Splits a into two halves of 12 bits that can be multiplied exactly
+fn split(a: vec4<f32>) -> Df4 {
+    let t = 4097.0 * a * params.one;
+    let hi = t - (t - a);
+    return Df4(hi, a - hi);
+}
+
+// a * b and its rounding error
+fn two_prod(a: vec4<f32>, b: vec4<f32>) -> Df4 {
+    let p = a * b;
+    let a_split = split(a);
+    let b_split = split(b);
+    let e = ((a_split.hi * b_split.hi - p) + a_split.hi * b_split.lo + a_split.lo * b_split.hi) + a_split.lo * b_split.lo;
+    return Df4(p, e);
+}
+
+fn df_add(a: Df4, b: Df4) -> Df4 {
+    let s = two_sum(a.hi, b.hi);
+    let t = two_sum(a.lo, b.lo);
+    let r = quick_two_sum(s.hi, s.lo + t.hi);
+    return quick_two_sum(r.hi, r.lo + t.lo);
+}
+
+fn df_neg(a: Df4) -> Df4 {
+    return Df4(-a.hi, -a.lo);
+}
+
+fn df_mul(a: Df4, b: Df4) -> Df4 {
+    let p = two_prod(a.hi, b.hi);
+    return quick_two_sum(p.hi, p.lo + (a.hi * b.lo + a.lo * b.hi));
 }
 
-// Must be the same as lib.rs
+fn df_mat_mul(m: mat4x4<f32>, v: Df4) -> Df4 {
+    var result = df_mul(df(m[0]), df_splat(v, 0u));
+    result = df_add(result, df_mul(df(m[1]), df_splat(v, 1u)));
+    result = df_add(result, df_mul(df(m[2]), df_splat(v, 2u)));
+    return df_add(result, df_mul(df(m[3]), df_splat(v, 3u)));
+}
+
+// Multiplies each part by a unit quaternion, which is exact
+fn df_unit_mul(unit: vec4<f32>, q: Df4) -> Df4 {
+    return Df4(quaternion_mul(unit, q.hi), quaternion_mul(unit, q.lo));
+}
+
+fn quaternion_mul_df64(a: Df4, b: Df4) -> Df4 {
+    // ab = a.x b + a.y ib + a.z jb + a.w kb
+    var result = df_mul(df_splat(a, 0u), b);
+    result = df_add(result, df_mul(df_splat(a, 1u), df_unit_mul(vec4(0.0, 1.0, 0.0, 0.0), b)));
+    result = df_add(result, df_mul(df_splat(a, 2u), df_unit_mul(vec4(0.0, 0.0, 1.0, 0.0), b)));
+    return df_add(result, df_mul(df_splat(a, 3u), df_unit_mul(vec4(0.0, 0.0, 0.0, 1.0), b)));
+}
+
+// mandelbrot with z and c in double precision. The derivatives don't need it
+fn mandelbrot_df64(pos: Df4) -> Escape {
+    var iters = 0u;
+    var c = pos;
+    var dc = 1.0;
+    if params.julia != 0u {
+        c = df(params.julia_c);
+        dc = 0.0;
+    }
+    var z = pos;
+    var derivatives = start_derivatives();
+    var flips_done = 0u;
+
+    while iters <= params.max_iterations {
+        if flip_applies(iters) {
+            let flip = params.flips[flips_done % params.flip_count];
+            z = df_mat_mul(flip, z);
+            derivatives = flip_derivatives(derivatives, flip);
+            flips_done++;
+        }
+
+        derivatives = step_derivatives(derivatives, z.hi, dc);
+
+        if params.power == 2.0 {
+            z = df_add(quaternion_mul_df64(z, z), c);
+        } else {
+            // There's no double precision polar form
+            z = df_add(df(quaternion_pow(z.hi, params.power)), c);
+        }
+
+        let z2 = dot(z.hi, z.hi);
+        if z2 > params.bailout {
+            break
+        }
+
+        iters++;
+    }
+
+    return escape(z.hi, derivatives, iters);
+}
+
+fn to_quat_df64(pos: Df4) -> Df4 {
+    let in_slice = df_add(pos, df(vec4(0.0, 0.0, 0.0, params.slice_w)));
+    return df_mat_mul(params.slice_rotation, in_slice);
+}
+
+fn moved_df64(pos: Df4, offset: vec4<f32>) -> Df4 {
+    return df_add(pos, df(offset));
+}
+
+fn DE_df64(pos: Df4) -> f32 {
+    let escape = mandelbrot_df64(to_quat_df64(pos));
+    var distance = escape.distance;
+    if params.estimator == ESTIMATOR_GRADIENT {
+        distance = gradient_estimate(
+            escape,
+            array<Escape, 3>(
+                mandelbrot_df64(to_quat_df64(moved_df64(pos, gradient_step(0u)))),
+                mandelbrot_df64(to_quat_df64(moved_df64(pos, gradient_step(1u)))),
+                mandelbrot_df64(to_quat_df64(moved_df64(pos, gradient_step(2u)))),
+            ),
+            array<Escape, 3>(
+                mandelbrot_df64(to_quat_df64(moved_df64(pos, -gradient_step(0u)))),
+                mandelbrot_df64(to_quat_df64(moved_df64(pos, -gradient_step(1u)))),
+                mandelbrot_df64(to_quat_df64(moved_df64(pos, -gradient_step(2u)))),
+            ),
+        );
+    }
+
+    return params.fudge * distance;
+}
+
+fn normals_df64(pos: Df4) -> vec4<f32> {
+    let gradient = vec3(
+        DE_df64(moved_df64(pos, gradient_step(0u))) - DE_df64(moved_df64(pos, -gradient_step(0u))),
+        DE_df64(moved_df64(pos, gradient_step(1u))) - DE_df64(moved_df64(pos, -gradient_step(1u))),
+        DE_df64(moved_df64(pos, gradient_step(2u))) - DE_df64(moved_df64(pos, -gradient_step(2u))),
+    );
+    return normalize(cam.rot * vec4(gradient, 0.0));
+}
+
+fn cam_pos_df64() -> Df4 {
+    return Df4(cam.pos, cam.pos_lo);
+}
+
+fn on_hit_df64(pos: Df4) -> vec3<f32> {
+    if params.shading == SHADING_NORMALS {
+        return normals_df64(pos).xyz * 0.5 + 0.5;
+    } else if params.shading == SHADING_PHONG {
+        return phong(pos.hi, normals_df64(pos));
+    } else if params.shading == SHADING_DEPTH {
+        let offset = df_add(pos, df_neg(cam_pos_df64()));
+        return vec3(1.0 / (1.0 + length(offset.hi)));
+    } else {
+        let iterations = mandelbrot_df64(to_quat_df64(pos)).iterations;
+        return vec3(f32(iterations) / f32(params.max_iterations));
+    }
+}
+
+fn get_color_df64(local_pos: vec3<f32>) -> vec3<f32> {
+    let ray_direction = normalize(local_pos);
+    // Unlike get_color this starts at the camera itself, as the near plane
+    // is further away than the details double precision is for
+    var ray_pos = cam_pos_df64();
+
+    var distance = DE_df64(ray_pos);
+    var steps = 0u;
+
+    while steps <= params.max_steps && distance > params.min_distance && distance < params.max_distance {
+        // Rotating the step in double precision too, so the tiny sideways
+        // parts of it don't get rounded away
+        ray_pos = df_add(ray_pos, df_mat_mul(cam.rot, df(vec4(ray_direction * distance, 0.0))));
+        distance = DE_df64(ray_pos);
+        steps++;
+    }
+
+    if params.shading == SHADING_STEPS {
+        return vec3(f32(steps) / f32(params.max_steps), 0.0, 0.0);
+    } else if distance <= params.min_distance {
+        return on_hit_df64(ray_pos);
+    } else {
+        return vec3(0.0);
+    }
+}
+
+// mandelbrot for the point offset away from the reference point in
+// quaternion space, iterating only its difference delta from the reference
+// orbit. See perturbation.rs for how that works
+fn mandelbrot_perturbed(offset: vec4<f32>) -> Escape {
+    var iters = 0u;
+    // How far c is from the reference's c
+    var delta_c = offset;
+    var dc = 1.0;
+    if params.julia != 0u {
+        delta_c = vec4(0.0);
+        dc = 0.0;
+    }
+    var delta = offset;
+    // Where the orbit being followed starts in orbit.points, how long it is
+    // and where in it we are
+    var start = 0u;
+    var len = orbit.main_len;
+    var m = 0u;
+    var derivatives = start_derivatives();
+    var flips_done = 0u;
+
+    // Powers other than 2 can't be perturbed, so those iterate z itself
+    // like mandelbrot does, which is also what happens when the reference
+    // runs out and we can't rebase
+    var perturbed = params.power == 2.0;
+    var c = orbit.points[0] + offset;
+    if params.julia != 0u {
+        c = params.julia_c;
+    }
+    var z = orbit.points[0] + offset;
+
+    while iters <= params.max_iterations {
+        var z_ref = orbit.points[start + m];
+        if flip_applies(iters) {
+            let flip = params.flips[flips_done % params.flip_count];
+            z = flip * z;
+            z_ref = flip * z_ref;
+            delta = flip * delta;
+            derivatives = flip_derivatives(derivatives, flip);
+            flips_done++;
+        }
+
+        derivatives = step_derivatives(derivatives, z, dc);
+
+        if perturbed {
+            // (Z + δ)² + C + δc = Z² + C + (Zδ + δZ + δ² + δc)
+            delta = quaternion_mul(z_ref, delta) + quaternion_mul(delta, z_ref) + quaternion_mul(delta, delta) + delta_c;
+            m++;
+            z = orbit.points[start + m] + delta;
+        } else if params.power == 2.0 {
+            z = quaternion_mul(z, z) + c;
+        } else {
+            z = quaternion_pow(z, params.power) + c;
+        }
+
+        let z2 = dot(z, z);
+        if z2 > params.bailout {
+            break
+        }
+
+        iters++;
+
+        let at_end = m + 1u >= len;
+        if perturbed && (z2 < dot(delta, delta) || at_end) {
+            if iters % orbit.rebase_period == 0u {
+                // Carries on from the rebase orbit's 0, so delta is all of z
+                delta = z;
+                start = orbit.main_len;
+                len = orbit.rebase_len;
+                m = 0u;
+            } else if at_end {
+                perturbed = false;
+            }
+        }
+    }
+
+    return escape(z, derivatives, iters);
+}
+
+// Offsets from the camera don't move along w to the slice, they only get
+// rotated
+fn offset_to_quat(offset: vec4<f32>) -> vec4<f32> {
+    return params.slice_rotation * offset;
+}
+
+fn DE_perturbed(offset: vec4<f32>) -> f32 {
+    let escape = mandelbrot_perturbed(offset_to_quat(offset));
+    var distance = escape.distance;
+    if params.estimator == ESTIMATOR_GRADIENT {
+        distance = gradient_estimate(
+            escape,
+            array<Escape, 3>(
+                mandelbrot_perturbed(offset_to_quat(offset + gradient_step(0u))),
+                mandelbrot_perturbed(offset_to_quat(offset + gradient_step(1u))),
+                mandelbrot_perturbed(offset_to_quat(offset + gradient_step(2u))),
+            ),
+            array<Escape, 3>(
+                mandelbrot_perturbed(offset_to_quat(offset - gradient_step(0u))),
+                mandelbrot_perturbed(offset_to_quat(offset - gradient_step(1u))),
+                mandelbrot_perturbed(offset_to_quat(offset - gradient_step(2u))),
+            ),
+        );
+    }
+
+    return params.fudge * distance;
+}
+
+fn normals_perturbed(offset: vec4<f32>) -> vec4<f32> {
+    let gradient = vec3(
+        DE_perturbed(offset + gradient_step(0u)) - DE_perturbed(offset - gradient_step(0u)),
+        DE_perturbed(offset + gradient_step(1u)) - DE_perturbed(offset - gradient_step(1u)),
+        DE_perturbed(offset + gradient_step(2u)) - DE_perturbed(offset - gradient_step(2u)),
+    );
+    return normalize(cam.rot * vec4(gradient, 0.0));
+}
+
+fn on_hit_perturbed(offset: vec4<f32>) -> vec3<f32> {
+    if params.shading == SHADING_NORMALS {
+        return normals_perturbed(offset).xyz * 0.5 + 0.5;
+    } else if params.shading == SHADING_PHONG {
+        return phong(cam.pos + offset, normals_perturbed(offset));
+    } else if params.shading == SHADING_DEPTH {
+        return vec3(1.0 / (1.0 + length(offset)));
+    } else {
+        let iterations = mandelbrot_perturbed(offset_to_quat(offset)).iterations;
+        return vec3(f32(iterations) / f32(params.max_iterations));
+    }
+}
+
+// get_color with the ray marched as an offset from the camera
+fn get_color_perturbed(real_pos: vec4<f32>) -> vec3<f32> {
+    let ray_direction = normalize(real_pos);
+    // Starts at the camera, like get_color_df64
+    var offset = vec4(0.0);
+
+    var distance = DE_perturbed(offset);
+    var steps = 0u;
+
+    while steps <= params.max_steps && distance > params.min_distance && distance < params.max_distance {
+        offset += ray_direction * distance;
+        distance = DE_perturbed(offset);
+        steps++;
+    }
+
+    if params.shading == SHADING_STEPS {
+        return vec3(f32(steps) / f32(params.max_steps), 0.0, 0.0);
+    } else if distance <= params.min_distance {
+        return on_hit_perturbed(offset);
+    } else {
+        return vec3(0.0);
+    }
+}
+
+// Must be the same as `Precision` in params.rs
+const PRECISION_SINGLE = 0u;
+const PRECISION_DOUBLE = 1u;
+const PRECISION_PERTURBATION = 2u;
+
+// Must be the same as camera.rs
 const SCALE = 0.0001;
 
 @fragment
@@ -184,5 +871,10 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     /*     get_color(pos.w) */
     /* ) / 5.0; */
     /* return vec4<f32>(color, 1.0); */
+    if params.float_precision == PRECISION_DOUBLE {
+        return vec4(get_color_df64(in.local_pos), 1.0);
+    } else if params.float_precision == PRECISION_PERTURBATION {
+        return vec4(get_color_perturbed(in.real_pos), 1.0);
+    }
     return vec4(get_color(in.real_pos), 1.0);
 }
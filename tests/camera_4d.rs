@@ -0,0 +1,78 @@
+//! A camera turned and moved off into 4D has to see the same as a camera in
+//! the 3D slice with the slice turned and moved the same way, which checks
+//! the 4D ray marching against the slicing that was there first.
+//!
+//! The GPU test needs a graphics adapter, so it only runs with
+//! `cargo test -- --ignored`.
+
+use cgmath::{Matrix4, Vector4};
+use mandelbrot::{
+    cpu, headless::HeadlessRenderer, image::Image, rotation::Rotation4, Camera, Params, Shading,
+};
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 36;
+
+/// A camera in the slice and a 4D camera that should see the same, with the
+/// parameters each of them renders with.
+fn cameras() -> [(Camera, Params); 2] {
+    let params = Params {
+        // Normals and lighting are in world space, which the two don't share
+        shading: Shading::Iterations,
+        ..Params::default()
+    };
+    let slice = Rotation4::towards_w(0, 0.5) * Rotation4::towards_w(2, -0.3);
+    let slice_w = 0.2;
+
+    let sliced = Camera::new([0.3, -0.2, -2.5], 0.2, -0.1);
+    let sliced_params = Params {
+        slice_rotation: Matrix4::from(slice).into(),
+        slice_w,
+        ..params.clone()
+    };
+
+    let [x, y, z, _] = sliced.position().to_f32();
+    let pos: [f32; 4] = slice.rotate(Vector4::new(x, y, z, slice_w)).into();
+    let mut camera = Camera::new(pos, 0.0, 0.0);
+    camera.set_orientation(slice * sliced.orientation());
+
+    [(sliced, sliced_params), (camera, params)]
+}
+
+/// Fails if more than a few pixels differ noticeably, for the rays that
+/// only just hit or miss the set.
+fn assert_similar(a: &Image, b: &Image) {
+    let differing = a
+        .pixels
+        .chunks_exact(4)
+        .zip(b.pixels.chunks_exact(4))
+        .filter(|(a, b)| a.iter().zip(b.iter()).any(|(a, b)| a.abs_diff(*b) > 8))
+        .count();
+    assert!(
+        differing <= (WIDTH * HEIGHT / 100) as usize,
+        "{differing} pixels differ"
+    );
+}
+
+#[test]
+fn cpu_4d_camera_matches_rotated_slice() {
+    let [(sliced, sliced_params), (camera, params)] = cameras();
+    assert_similar(
+        &cpu::render(&sliced, &sliced_params, WIDTH, HEIGHT),
+        &cpu::render(&camera, &params, WIDTH, HEIGHT),
+    );
+}
+
+#[test]
+#[ignore = "needs a graphics adapter, run with `cargo test -- --ignored`"]
+fn gpu_4d_camera_matches_rotated_slice() {
+    let renderer = pollster::block_on(HeadlessRenderer::new_fallback()).unwrap();
+
+    let [(sliced, sliced_params), (camera, params)] = cameras();
+    assert_similar(
+        &renderer
+            .render(&sliced, &sliced_params, WIDTH, HEIGHT)
+            .unwrap(),
+        &renderer.render(&camera, &params, WIDTH, HEIGHT).unwrap(),
+    );
+}
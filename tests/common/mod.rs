@@ -0,0 +1,110 @@
+//! Runs single functions from shader.wgsl on the GPU, so tests can check
+//! them against their Rust counterparts.
+
+use std::sync::mpsc;
+
+use wgpu::util::DeviceExt;
+
+/// Appends `entry_point_source` to the shader and runs its compute entry
+/// point `entry_point` once per output.
+///
+/// The entry point gets `inputs` bound as `test_inputs` at binding 8 and
+/// writes `output_len` values into `test_outputs` at binding 9, both
+/// `array<vec4<f32>>`. With `bind_params` the params uniform is bound too,
+/// with every field set to 1.0, which is enough for functions that only
+/// read `params.one`. Returns `None` when there is no graphics adapter.
+pub fn run_compute(
+    entry_point_source: &str,
+    entry_point: &str,
+    inputs: &[[f32; 4]],
+    output_len: usize,
+    bind_params: bool,
+) -> Option<Vec<[f32; 4]>> {
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+    let adapter = pollster::block_on(instance.request_adapter(&Default::default()))?;
+    let (device, queue) =
+        pollster::block_on(adapter.request_device(&Default::default(), None)).ok()?;
+
+    let source = include_str!("../../src/shader.wgsl").to_owned() + entry_point_source;
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Test shader"),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    });
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("Test pipeline"),
+        layout: None,
+        module: &shader,
+        entry_point,
+    });
+
+    let output_size = (output_len * std::mem::size_of::<[f32; 4]>()) as wgpu::BufferAddress;
+
+    let input_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Inputs"),
+        contents: bytemuck::cast_slice(inputs),
+        usage: wgpu::BufferUsages::STORAGE,
+    });
+    let output_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Outputs"),
+        size: output_size,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+    let read_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Read back"),
+        size: output_size,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    // Comfortably bigger than the params uniform
+    let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Params"),
+        contents: bytemuck::cast_slice(&[1.0f32; 1024]),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+
+    let mut entries = vec![
+        wgpu::BindGroupEntry {
+            binding: 8,
+            resource: input_buffer.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+            binding: 9,
+            resource: output_buffer.as_entire_binding(),
+        },
+    ];
+    if bind_params {
+        entries.push(wgpu::BindGroupEntry {
+            binding: 2,
+            resource: params_buffer.as_entire_binding(),
+        });
+    }
+
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &pipeline.get_bind_group_layout(0),
+        entries: &entries,
+    });
+
+    let mut encoder = device.create_command_encoder(&Default::default());
+    {
+        let mut pass = encoder.begin_compute_pass(&Default::default());
+        pass.set_pipeline(&pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.dispatch_workgroups(output_len as u32, 1, 1);
+    }
+    encoder.copy_buffer_to_buffer(&output_buffer, 0, &read_buffer, 0, output_size);
+    queue.submit(std::iter::once(encoder.finish()));
+
+    let slice = read_buffer.slice(..);
+    let (sender, receiver) = mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        sender.send(result).unwrap()
+    });
+    device.poll(wgpu::Maintain::Wait);
+    receiver.recv().unwrap().unwrap();
+
+    let mapped = slice.get_mapped_range();
+    Some(bytemuck::cast_slice(&mapped).to_vec())
+}
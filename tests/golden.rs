@@ -0,0 +1,382 @@
+//! Renders a fixed set of camera poses and compares them to the reference
+//! images in `tests/golden`.
+//!
+//! The references come from the CPU renderer, which the GPU one is then
+//! held to with a little more tolerance. The GPU test needs a graphics
+//! adapter, so it only runs with `cargo test -- --ignored`.
+//!
+//! Run with `UPDATE_GOLDEN=1` to overwrite the references after an
+//! intentional change to the rendering, and check the new images in.
+
+use std::path::{Path, PathBuf};
+
+use mandelbrot::{
+    cpu, flip::FlipSchedule, headless::HeadlessRenderer, image::Image, rotation, Camera,
+    Derivative, Estimator, Params, Precision, Shading,
+};
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 36;
+
+struct Pose {
+    name: &'static str,
+    pos: [f32; 3],
+    // In degrees
+    yaw: f32,
+    pitch: f32,
+    fov: f32,
+    params: Params,
+}
+
+fn poses() -> Vec<Pose> {
+    vec![
+        Pose {
+            name: "front",
+            pos: [0.0, 0.0, -3.0],
+            yaw: 0.0,
+            pitch: 0.0,
+            fov: 90.0,
+            params: Params::default(),
+        },
+        Pose {
+            name: "side",
+            pos: [3.0, 0.0, 0.0],
+            yaw: 90.0,
+            pitch: 0.0,
+            fov: 90.0,
+            params: Params::default(),
+        },
+        Pose {
+            name: "top",
+            pos: [0.0, 3.0, 0.0],
+            yaw: 0.0,
+            pitch: -90.0,
+            fov: 90.0,
+            params: Params::default(),
+        },
+        Pose {
+            name: "close",
+            pos: [-0.5, 0.3, -2.5],
+            yaw: 10.0,
+            pitch: -5.0,
+            fov: 90.0,
+            params: Params::default(),
+        },
+        Pose {
+            name: "few_iterations",
+            pos: [0.0, 0.0, -3.0],
+            yaw: 0.0,
+            pitch: 0.0,
+            fov: 90.0,
+            params: Params {
+                max_iterations: 8,
+                ..Params::default()
+            },
+        },
+        Pose {
+            name: "wide_fov",
+            pos: [0.0, 0.0, -2.0],
+            yaw: 0.0,
+            pitch: 0.0,
+            fov: 120.0,
+            params: Params::default(),
+        },
+        shaded("normals", Shading::Normals),
+        shaded("phong", Shading::Phong),
+        shaded("depth", Shading::Depth),
+        shaded("iterations", Shading::Iterations),
+        Pose {
+            name: "rotated_slice",
+            pos: [0.0, 0.0, -3.0],
+            yaw: 0.0,
+            pitch: 0.0,
+            fov: 90.0,
+            params: Params {
+                slice_w: 0.2,
+                slice_rotation: rotation::from_angles([0.0, 0.0, 0.6, 0.0, 0.3, 0.0]).into(),
+                ..Params::default()
+            },
+        },
+        Pose {
+            name: "julia",
+            pos: [0.0, 0.0, -3.0],
+            yaw: 0.0,
+            pitch: 0.0,
+            fov: 90.0,
+            params: Params {
+                julia: true,
+                julia_c: [-0.2, 0.6, 0.2, 0.0],
+                shading: Shading::Normals,
+                ..Params::default()
+            },
+        },
+        Pose {
+            name: "alternating_flips",
+            pos: [0.0, 0.0, -3.0],
+            yaw: 0.0,
+            pitch: 0.0,
+            fov: 90.0,
+            params: Params {
+                flips: vec!["x,-z,-y,w".parse().unwrap(), "x,-w,z,-y".parse().unwrap()],
+                flip_schedule: FlipSchedule::EveryNth(2),
+                shading: Shading::Normals,
+                ..Params::default()
+            },
+        },
+        Pose {
+            name: "power_3",
+            pos: [0.0, 0.0, -3.0],
+            yaw: 0.0,
+            pitch: 0.0,
+            fov: 90.0,
+            params: Params {
+                power: 3.0,
+                shading: Shading::Normals,
+                ..Params::default()
+            },
+        },
+        Pose {
+            name: "power_5_5",
+            pos: [0.0, 0.0, -3.0],
+            yaw: 0.0,
+            pitch: 0.0,
+            fov: 90.0,
+            params: Params {
+                power: 5.5,
+                shading: Shading::Normals,
+                ..Params::default()
+            },
+        },
+        Pose {
+            name: "linear",
+            pos: [0.0, 0.0, -3.0],
+            yaw: 0.0,
+            pitch: 0.0,
+            fov: 90.0,
+            params: Params {
+                estimator: Estimator::Linear,
+                ..Params::default()
+            },
+        },
+        Pose {
+            name: "gradient",
+            pos: [0.0, 0.0, -3.0],
+            yaw: 0.0,
+            pitch: 0.0,
+            fov: 90.0,
+            params: Params {
+                estimator: Estimator::Gradient,
+                ..Params::default()
+            },
+        },
+        Pose {
+            name: "fudge",
+            pos: [0.0, 0.0, -3.0],
+            yaw: 0.0,
+            pitch: 0.0,
+            fov: 90.0,
+            params: Params {
+                fudge: 0.5,
+                ..Params::default()
+            },
+        },
+        Pose {
+            name: "double",
+            pos: [0.0, 0.0, -3.0],
+            yaw: 0.0,
+            pitch: 0.0,
+            fov: 90.0,
+            params: Params {
+                precision: Precision::Double,
+                shading: Shading::Normals,
+                ..Params::default()
+            },
+        },
+        Pose {
+            // Far past where single precision only renders noise
+            name: "deep_zoom",
+            pos: [-0.3, 0.0, -3.0],
+            yaw: 0.0,
+            pitch: 0.0,
+            fov: 0.00001,
+            params: Params {
+                precision: Precision::Double,
+                min_distance: 1e-10,
+                ..Params::default()
+            },
+        },
+        Pose {
+            // Perturbation needs the camera about as close to the surface as
+            // the details it looks at
+            name: "perturbation",
+            pos: [-0.3, 0.0, -0.59992],
+            yaw: 20.0,
+            pitch: 30.0,
+            fov: 0.001,
+            params: Params {
+                precision: Precision::Perturbation,
+                min_distance: 1e-11,
+                shading: Shading::Phong,
+                ..Params::default()
+            },
+        },
+        Pose {
+            name: "jacobian",
+            pos: [0.0, 0.0, -3.0],
+            yaw: 0.0,
+            pitch: 0.0,
+            fov: 90.0,
+            params: Params {
+                derivative: Derivative::Jacobian,
+                flips: vec!["x,-z,-y,w".parse().unwrap(), "x,-w,z,-y".parse().unwrap()],
+                flip_schedule: FlipSchedule::EveryNth(2),
+                shading: Shading::Normals,
+                ..Params::default()
+            },
+        },
+    ]
+}
+
+/// The front pose with another shading mode.
+fn shaded(name: &'static str, shading: Shading) -> Pose {
+    Pose {
+        name,
+        pos: [0.0, 0.0, -3.0],
+        yaw: 0.0,
+        pitch: 0.0,
+        fov: 90.0,
+        params: Params {
+            shading,
+            ..Params::default()
+        },
+    }
+}
+
+impl Pose {
+    fn camera(&self) -> Camera {
+        Camera::new(self.pos, self.yaw.to_radians(), self.pitch.to_radians())
+            .with_fov(self.fov.to_radians())
+    }
+
+    fn golden_path(&self) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/golden")
+            .join(format!("{}.png", self.name))
+    }
+}
+
+/// How far off a rendering may be from its reference.
+struct Tolerance {
+    /// Largest allowed difference of a single channel of a pixel
+    per_pixel: u8,
+    /// Fraction of pixels that may go over `per_pixel`, for the chaotic
+    /// pixels right at the edge of the set
+    outliers: f32,
+}
+
+fn compare(actual: &Image, expected: &Image, tolerance: &Tolerance) -> Result<(), String> {
+    if (actual.width, actual.height) != (expected.width, expected.height) {
+        return Err(format!(
+            "size is {}x{} but expected {}x{}",
+            actual.width, actual.height, expected.width, expected.height
+        ));
+    }
+
+    let mut outliers = 0;
+    let mut worst = 0;
+    for (a, e) in actual
+        .pixels
+        .chunks_exact(4)
+        .zip(expected.pixels.chunks_exact(4))
+    {
+        let diff = a.iter().zip(e).map(|(a, e)| a.abs_diff(*e)).max().unwrap();
+        worst = worst.max(diff);
+        if diff > tolerance.per_pixel {
+            outliers += 1;
+        }
+    }
+
+    let allowed = (tolerance.outliers * (actual.width * actual.height) as f32) as usize;
+    if outliers > allowed {
+        return Err(format!(
+            "{outliers} pixels differ by more than {} (up to {worst}), only {allowed} may",
+            tolerance.per_pixel
+        ));
+    }
+
+    Ok(())
+}
+
+/// Renders every pose with `render` and checks it against its reference,
+/// reporting all mismatches at once.
+fn check_poses(backend: &str, tolerance: Tolerance, render: impl Fn(&Pose) -> Image) {
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let failure_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    let mut failures = vec![];
+
+    for pose in poses() {
+        let actual = render(&pose);
+        let path = pose.golden_path();
+
+        if update {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            actual.save_png(&path).unwrap();
+            continue;
+        }
+
+        let expected = Image::load_png(&path)
+            .unwrap_or_else(|e| panic!("couldn't load {}: {e}", path.display()));
+        if let Err(e) = compare(&actual, &expected, &tolerance) {
+            std::fs::create_dir_all(&failure_dir).unwrap();
+            let failure_path = failure_dir.join(format!("{}-{backend}.png", pose.name));
+            actual.save_png(&failure_path).unwrap();
+            failures.push(format!(
+                "{}: {e}, saved as {}",
+                pose.name,
+                failure_path.display()
+            ));
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{backend} renders differ from the references:\n{}",
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn cpu_matches_golden_images() {
+    let tolerance = Tolerance {
+        per_pixel: 4,
+        outliers: 0.005,
+    };
+
+    check_poses("cpu", tolerance, |pose| {
+        cpu::render(&pose.camera(), &pose.params, WIDTH, HEIGHT)
+    });
+}
+
+#[test]
+#[ignore = "needs a graphics adapter, run with `cargo test -- --ignored`"]
+fn gpu_matches_golden_images() {
+    // The references come from the CPU renderer, so they are only
+    // regenerated by `cpu_matches_golden_images`
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        return;
+    }
+
+    let renderer = pollster::block_on(HeadlessRenderer::new_fallback()).unwrap();
+
+    // GPUs are allowed to do f32 math slightly differently
+    let tolerance = Tolerance {
+        per_pixel: 8,
+        outliers: 0.02,
+    };
+
+    check_poses("gpu", tolerance, |pose| {
+        renderer
+            .render(&pose.camera(), &pose.params, WIDTH, HEIGHT)
+            .unwrap()
+    });
+}
@@ -0,0 +1,79 @@
+//! Runs the double-float arithmetic from shader.wgsl on the GPU and checks
+//! it against `Df64`. Shader compilers are allowed to reorder and fuse
+//! float operations, which would quietly throw the extra precision away.
+//!
+//! Needs a graphics adapter, so it only runs with `cargo test -- --ignored`.
+
+mod common;
+
+use mandelbrot::df64::Df64;
+
+// Appended to the shader so the `Df4` functions can be called on their own.
+// Every case is `a` then `b` as hi and lo, and gives `a + b` then `a * b`
+const TEST_ENTRY_POINT: &str = "
+@group(0) @binding(8)
+var<storage, read> test_inputs: array<vec4<f32>>;
+
+@group(0) @binding(9)
+var<storage, read_write> test_outputs: array<vec4<f32>>;
+
+@compute @workgroup_size(1)
+fn test_df64(@builtin(global_invocation_id) id: vec3<u32>) {
+    let n = id.x / 4u;
+    let a = Df4(test_inputs[4u * n], test_inputs[4u * n + 1u]);
+    let b = Df4(test_inputs[4u * n + 2u], test_inputs[4u * n + 3u]);
+    let sum = df_add(a, b);
+    let product = df_mul(a, b);
+    var outputs = array(sum.hi, sum.lo, product.hi, product.lo);
+    test_outputs[id.x] = outputs[id.x % 4u];
+}
+";
+
+fn split(values: [f64; 4]) -> [[f32; 4]; 2] {
+    let values = values.map(Df64::from);
+    [values.map(|x| x.hi), values.map(|x| x.lo)]
+}
+
+fn join(hi: [f32; 4], lo: [f32; 4]) -> [Df64; 4] {
+    [0, 1, 2, 3].map(|i| Df64::new(hi[i], lo[i]))
+}
+
+#[test]
+#[ignore = "needs a graphics adapter, run with `cargo test -- --ignored`"]
+fn shader_df64_matches_df64() {
+    // A deterministic spread of values that don't fit in an f32
+    let cases: Vec<([f64; 4], [f64; 4])> = (0..64)
+        .map(|n| {
+            let f = |m: i32| {
+                let x = ((n * 7 + m * 13) % 17) as f64 / 4.0 - 2.0;
+                x + x / 3.0 * 1e-9
+            };
+            ([f(1), f(2), f(3), f(4)], [f(5), f(6), f(7), f(8)])
+        })
+        .collect();
+
+    let inputs: Vec<[f32; 4]> = cases
+        .iter()
+        .flat_map(|&(a, b)| [split(a), split(b)].concat())
+        .collect();
+
+    let outputs = common::run_compute(
+        TEST_ENTRY_POINT,
+        "test_df64",
+        &inputs,
+        cases.len() * 4,
+        true,
+    )
+    .expect("no graphics adapter found");
+
+    for (&(a, b), gpu) in cases.iter().zip(outputs.chunks_exact(4)) {
+        let [a_df, b_df] = [a, b].map(|x| x.map(Df64::from));
+        let sum = join(gpu[0], gpu[1]);
+        let product = join(gpu[2], gpu[3]);
+
+        for i in 0..4 {
+            assert_eq!(sum[i], a_df[i] + b_df[i], "{} + {}", a[i], b[i]);
+            assert_eq!(product[i], a_df[i] * b_df[i], "{} * {}", a[i], b[i]);
+        }
+    }
+}
@@ -0,0 +1,69 @@
+//! Runs `quaternion_mul` from shader.wgsl on the GPU and checks it against
+//! `Quaternion`, so the set the shader renders is the real quaternion
+//! mandelbrot set.
+//!
+//! Needs a graphics adapter, so it only runs with `cargo test -- --ignored`.
+
+mod common;
+
+use mandelbrot::Quaternion;
+
+// Appended to the shader so `quaternion_mul` can be called on its own
+const TEST_ENTRY_POINT: &str = "
+@group(0) @binding(8)
+var<storage, read> test_inputs: array<vec4<f32>>;
+
+@group(0) @binding(9)
+var<storage, read_write> test_outputs: array<vec4<f32>>;
+
+@compute @workgroup_size(1)
+fn test_quaternion_mul(@builtin(global_invocation_id) id: vec3<u32>) {
+    test_outputs[id.x] = quaternion_mul(test_inputs[2u * id.x], test_inputs[2u * id.x + 1u]);
+}
+";
+
+fn gpu_quaternion_mul(pairs: &[(Quaternion, Quaternion)]) -> Option<Vec<Quaternion>> {
+    let inputs: Vec<[f32; 4]> = pairs
+        .iter()
+        .flat_map(|&(a, b)| [a, b])
+        .map(<[f32; 4]>::from)
+        .collect();
+
+    let outputs = common::run_compute(
+        TEST_ENTRY_POINT,
+        "test_quaternion_mul",
+        &inputs,
+        pairs.len(),
+        false,
+    )?;
+    Some(outputs.into_iter().map(Quaternion::from).collect())
+}
+
+#[test]
+#[ignore = "needs a graphics adapter, run with `cargo test -- --ignored`"]
+fn shader_quaternion_mul_matches_quaternion() {
+    let basis = [Quaternion::ONE, Quaternion::I, Quaternion::J, Quaternion::K];
+    let mut pairs: Vec<_> = basis
+        .iter()
+        .flat_map(|&a| basis.iter().map(move |&b| (a, b)))
+        .collect();
+
+    // A deterministic spread of less special values
+    for n in 0..64 {
+        let f = |m: i32| ((n * 7 + m * 13) % 17) as f32 / 4.0 - 2.0;
+        pairs.push((
+            Quaternion::new(f(1), f(2), f(3), f(4)),
+            Quaternion::new(f(5), f(6), f(7), f(8)),
+        ));
+    }
+
+    let outputs = gpu_quaternion_mul(&pairs).expect("no graphics adapter found");
+
+    for (&(a, b), gpu) in pairs.iter().zip(outputs) {
+        let cpu = a * b;
+        assert!(
+            (cpu - gpu).norm() <= 1e-5 * cpu.norm().max(1.0),
+            "{a:?} * {b:?}: shader gave {gpu:?}, expected {cpu:?}"
+        );
+    }
+}